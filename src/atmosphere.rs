@@ -0,0 +1,120 @@
+use cgmath::InnerSpace;
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct AtmosphereUniform {
+    sun: [f32; 4],
+    fog_color: [f32; 4],
+    fog: [f32; 4],
+    scattering: [f32; 4],
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum FogMode {
+    Off,
+    /// Ramps up between `Fog::start` and `Fog::end`.
+    Linear,
+    /// Thickens with `Fog::density`, thinning out with height.
+    Exponential,
+}
+
+impl FogMode {
+    pub fn next(self) -> Self {
+        match self {
+            FogMode::Off => FogMode::Linear,
+            FogMode::Linear => FogMode::Exponential,
+            FogMode::Exponential => FogMode::Off,
+        }
+    }
+}
+
+pub struct Fog {
+    pub mode: FogMode,
+    /// Only used without the sky, with it fog takes the color of the air.
+    pub color: [f32; 3],
+    pub start: f32,
+    pub end: f32,
+    /// Extinction per world unit at height 0.
+    pub density: f32,
+    /// How fast exponential fog thins out going up, 0 for the same
+    /// density everywhere.
+    pub height_falloff: f32,
+}
+
+/// Fog, and optionally a physically based sky (Rayleigh and Mie scattering)
+/// that replaces the clear color and tints distant surfaces towards the color
+/// of the air. Bound next to the camera, see atmosphere.wgsl.
+pub struct Atmosphere {
+    pub fog: Fog,
+    pub sky: bool,
+    /// Towards the sun, also what the scene is lit by.
+    pub sun_direction: cgmath::Vector3<f32>,
+    pub sun_intensity: f32,
+    /// Sizes the world for scattering, how far away the terrain looks.
+    pub meters_per_unit: f32,
+    /// Mie anisotropy, how much of the haze glows around the sun.
+    pub mie_g: f32,
+    pub buffer: wgpu::Buffer,
+}
+
+impl Atmosphere {
+    /// Without the sky fog blends into `clear_color`.
+    pub fn new(device: &wgpu::Device, clear_color: wgpu::Color) -> Self {
+        Self {
+            fog: Fog {
+                mode: FogMode::Exponential,
+                color: [
+                    clear_color.r as f32,
+                    clear_color.g as f32,
+                    clear_color.b as f32,
+                ],
+                start: 200.0,
+                end: 2000.0,
+                density: 0.002,
+                height_falloff: 0.02,
+            },
+            sky: true,
+            sun_direction: cgmath::Vector3::new(0.3, 1.0, 0.5).normalize(),
+            sun_intensity: 20.0,
+            meters_per_unit: 20.0,
+            mie_g: 0.76,
+            buffer: device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("Atmosphere Uniform Buffer"),
+                size: std::mem::size_of::<AtmosphereUniform>() as u64,
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            }),
+        }
+    }
+
+    pub fn update(&self, queue: &wgpu::Queue) {
+        let sun = self.sun_direction.normalize();
+        let mode = match self.fog.mode {
+            FogMode::Off => 0.0,
+            FogMode::Linear => 1.0,
+            FogMode::Exponential => 2.0,
+        };
+        let [r, g, b] = self.fog.color;
+
+        queue.write_buffer(
+            &self.buffer,
+            0,
+            bytemuck::cast_slice(&[AtmosphereUniform {
+                sun: [sun.x, sun.y, sun.z, self.sun_intensity],
+                fog_color: [r, g, b, mode],
+                fog: [
+                    self.fog.start,
+                    self.fog.end,
+                    self.fog.density,
+                    self.fog.height_falloff,
+                ],
+                scattering: [
+                    if self.sky { 1.0 } else { 0.0 },
+                    self.meters_per_unit,
+                    self.mie_g,
+                    0.0,
+                ],
+            }]),
+        );
+    }
+}
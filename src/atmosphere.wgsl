@@ -0,0 +1,153 @@
+// Fog, sky and aerial perspective. Include after declaring
+// `var<uniform> atmosphere: AtmosphereUniform`.
+
+let PI: f32 = 3.14159265;
+let PLANET_RADIUS: f32 = 6371000.0;
+let ATMOSPHERE_RADIUS: f32 = 6471000.0;
+// Scale heights of air molecules and aerosols, in meters
+let RAYLEIGH_HEIGHT: f32 = 8000.0;
+let MIE_HEIGHT: f32 = 1200.0;
+
+// Scattering coefficients at sea level, per meter
+fn rayleigh_coefficients() -> vec3<f32> {
+    return vec3<f32>(0.0000058, 0.0000135, 0.0000331);
+}
+
+fn mie_coefficient() -> f32 {
+    return 0.000021;
+}
+
+fn rayleigh_phase(mu: f32) -> f32 {
+    return 3.0 / (16.0 * PI) * (1.0 + mu * mu);
+}
+
+// Cornette-Shanks
+fn mie_phase(mu: f32) -> f32 {
+    let g = atmosphere.scattering.z;
+    let gg = g * g;
+    return 3.0 / (8.0 * PI) * ((1.0 - gg) * (1.0 + mu * mu))
+        / ((2.0 + gg) * pow(1.0 + gg - 2.0 * mu * g, 1.5));
+}
+
+// Distance from `origin` inside a sphere around the planet's center to where
+// the ray leaves it
+fn sphere_exit(origin: vec3<f32>, direction: vec3<f32>, radius: f32) -> f32 {
+    let b = dot(origin, direction);
+    let c = dot(origin, origin) - radius * radius;
+    return -b + sqrt(max(b * b - c, 0.0));
+}
+
+// Sunlight scattered once towards a viewer at ground level looking along
+// `direction`, through air that thins out exponentially with height
+fn sky_color(direction: vec3<f32>) -> vec3<f32> {
+    let sun = atmosphere.sun.xyz;
+    // Below the horizon there would be ground, keep the horizon's color
+    let view = normalize(vec3<f32>(direction.x, max(direction.y, 0.0), direction.z));
+    let origin = vec3<f32>(0.0, PLANET_RADIUS + 1.0, 0.0);
+    let step = sphere_exit(origin, view, ATMOSPHERE_RADIUS) / 16.0;
+    let rayleigh = rayleigh_coefficients();
+    let mie = mie_coefficient();
+
+    var depth_r = 0.0;
+    var depth_m = 0.0;
+    var total_r = vec3<f32>(0.0, 0.0, 0.0);
+    var total_m = vec3<f32>(0.0, 0.0, 0.0);
+    for (var i: i32 = 0; i < 16; i = i + 1) {
+        let position = origin + view * step * (f32(i) + 0.5);
+        let height = length(position) - PLANET_RADIUS;
+        let step_r = exp(-height / RAYLEIGH_HEIGHT) * step;
+        let step_m = exp(-height / MIE_HEIGHT) * step;
+        depth_r = depth_r + step_r;
+        depth_m = depth_m + step_m;
+
+        // Towards the sun, skipped where the planet is in the way
+        let b = dot(position, sun);
+        let shadowed = b < 0.0 && b * b - dot(position, position) + PLANET_RADIUS * PLANET_RADIUS > 0.0;
+        if (!shadowed) {
+            let light_step = sphere_exit(position, sun, ATMOSPHERE_RADIUS) / 8.0;
+            var light_r = 0.0;
+            var light_m = 0.0;
+            for (var j: i32 = 0; j < 8; j = j + 1) {
+                let light_position = position + sun * light_step * (f32(j) + 0.5);
+                let light_height = length(light_position) - PLANET_RADIUS;
+                light_r = light_r + exp(-light_height / RAYLEIGH_HEIGHT) * light_step;
+                light_m = light_m + exp(-light_height / MIE_HEIGHT) * light_step;
+            }
+
+            let optical_depth = rayleigh * (depth_r + light_r) + vec3<f32>(mie * 1.1 * (depth_m + light_m));
+            let attenuation = exp(-optical_depth);
+            total_r = total_r + attenuation * step_r;
+            total_m = total_m + attenuation * step_m;
+        }
+    }
+
+    let mu = dot(view, sun);
+    let sky = rayleigh_phase(mu) * rayleigh * total_r + mie_phase(mu) * mie * total_m;
+    let disk = select(0.0, 1.0, dot(direction, sun) > 0.99996);
+    return atmosphere.sun.w * (sky + vec3<f32>(disk));
+}
+
+// Air density relative to sea level at `height` world units, molecules in x
+// and aerosols in y
+fn air_density(height: f32) -> vec2<f32> {
+    let meters = max(height * atmosphere.scattering.y, 0.0);
+    return vec2<f32>(exp(-meters / RAYLEIGH_HEIGHT), exp(-meters / MIE_HEIGHT));
+}
+
+// What the air looking along `direction` converges to with distance, the
+// sunlight it scatters in over the light it absorbs
+fn in_scattered(direction: vec3<f32>, density: vec2<f32>) -> vec3<f32> {
+    let sun = atmosphere.sun.xyz;
+    let mu = dot(direction, sun);
+    let rayleigh = rayleigh_coefficients() * density.x;
+    let mie = mie_coefficient() * density.y;
+    let scattering = rayleigh * rayleigh_phase(mu) + vec3<f32>(mie * mie_phase(mu));
+    let extinction = rayleigh + vec3<f32>(mie * 1.1);
+    // Fades out as the sun sets
+    let daylight = clamp(sun.y * 10.0 + 0.5, 0.0, 1.0);
+    return atmosphere.sun.w * daylight * scattering / extinction;
+}
+
+// How much fog is between `view_pos` and `position`, 0..1
+fn fog_amount(position: vec3<f32>, view_pos: vec3<f32>) -> f32 {
+    let mode = atmosphere.fog_color.w;
+    let distance = distance(position, view_pos);
+    var amount = 0.0;
+    if (mode > 1.5) {
+        // Density falls off with height as exp(-falloff * y), integrated
+        // along the ray
+        let falloff = atmosphere.fog.w;
+        let rise = falloff * (position.y - view_pos.y);
+        var optical_depth = atmosphere.fog.z * exp(-falloff * view_pos.y) * distance;
+        if (abs(rise) > 0.0001) {
+            optical_depth = optical_depth * (1.0 - exp(-rise)) / rise;
+        }
+        amount = 1.0 - exp(-optical_depth);
+    } else {
+        if (mode > 0.5) {
+            amount = clamp((distance - atmosphere.fog.x) / (atmosphere.fog.y - atmosphere.fog.x), 0.0, 1.0);
+        }
+    }
+    return amount;
+}
+
+// `color` of a surface at `position` as seen from `view_pos`. With the sky on
+// the air in between dims it and adds blue, and the fog takes on the color of
+// the air.
+fn apply_atmosphere(color: vec3<f32>, position: vec3<f32>, view_pos: vec3<f32>) -> vec3<f32> {
+    let to_surface = position - view_pos;
+    let distance = length(to_surface);
+    let direction = to_surface / max(distance, 0.0001);
+
+    var result = color;
+    var fog_color = atmosphere.fog_color.rgb;
+    if (atmosphere.scattering.x > 0.5) {
+        let density = air_density((position.y + view_pos.y) * 0.5);
+        let rayleigh = rayleigh_coefficients() * density.x;
+        let extinction = rayleigh + vec3<f32>(mie_coefficient() * 1.1 * density.y);
+        let transmittance = exp(-extinction * distance * atmosphere.scattering.y);
+        fog_color = in_scattered(direction, density);
+        result = color * transmittance + fog_color * (1.0 - transmittance);
+    }
+    return mix(result, fog_color, fog_amount(position, view_pos));
+}
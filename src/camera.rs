@@ -2,6 +2,8 @@ use cgmath::*;
 use std::f32::consts::FRAC_PI_2;
 use winit::{dpi::PhysicalPosition, event::*};
 
+use crate::input;
+
 #[rustfmt::skip] pub const OPENGL_TO_WGPU_MATRIX: cgmath::Matrix4<f32> = cgmath::Matrix4::new(
     1.0, 0.0, 0.0, 0.0,
     0.0, 1.0, 0.0, 0.0,
@@ -9,11 +11,20 @@ use winit::{dpi::PhysicalPosition, event::*};
     0.0, 0.0, 0.5, 1.0,
 );
 
-#[derive(Debug)]
+/// Maps wgpu depth z to 1 - z, see `ReversedZ`.
+#[rustfmt::skip]
+pub const REVERSE_Z_MATRIX: cgmath::Matrix4<f32> = cgmath::Matrix4::new(
+    1.0, 0.0, 0.0, 0.0,
+    0.0, 1.0, 0.0, 0.0,
+    0.0, 0.0, -1.0, 0.0,
+    0.0, 0.0, 1.0, 1.0,
+);
+
+#[derive(Debug, Copy, Clone)]
 pub struct Camera {
     pub pos: Point3<f32>,
-    yaw: Rad<f32>,
-    pitch: Rad<f32>,
+    pub yaw: Rad<f32>,
+    pub pitch: Rad<f32>,
 }
 
 impl Camera {
@@ -29,26 +40,60 @@ impl Camera {
             pitch: pitch.into(),
         }
     }
-    pub fn view_mat(&self) -> Matrix4<f32> {
+
+    /// This camera mirrored by the horizontal plane at `height`, for planar
+    /// reflections. What it sees is upside down.
+    pub fn reflected(&self, height: f32) -> Camera {
+        Camera {
+            pos: Point3::new(self.pos.x, 2.0 * height - self.pos.y, self.pos.z),
+            yaw: self.yaw,
+            pitch: -self.pitch,
+        }
+    }
+
+    /// Partway from this camera to `other`, `t` from 0 to 1. Yaw isn't
+    /// wrapped, so this turns the way the camera did.
+    pub fn lerp(&self, other: &Camera, t: f32) -> Camera {
+        Camera {
+            pos: self.pos + (other.pos - self.pos) * t,
+            yaw: self.yaw + (other.yaw - self.yaw) * t,
+            pitch: self.pitch + (other.pitch - self.pitch) * t,
+        }
+    }
+
+    /// Unit vector the camera looks along.
+    pub fn direction(&self) -> Vector3<f32> {
         let (sin_pitch, cos_pitch) = self.pitch.0.sin_cos();
         let (sin_yaw, cos_yaw) = self.yaw.0.sin_cos();
-        Matrix4::look_to_rh(
-            self.pos,
-            Vector3::new(cos_pitch * cos_yaw, sin_pitch, cos_pitch * sin_yaw),
-            Vector3::unit_y(),
-        )
+        Vector3::new(cos_pitch * cos_yaw, sin_pitch, cos_pitch * sin_yaw)
+    }
+
+    pub fn view_mat(&self) -> Matrix4<f32> {
+        Matrix4::look_to_rh(self.pos, self.direction(), Vector3::unit_y())
+    }
+}
+
+/// Turns view space into clip space, kept in sync with the surface size by
+/// `resize`.
+pub trait Projection: std::fmt::Debug {
+    fn resize(&mut self, width: u32, height: u32);
+    fn proj_mat(&self) -> Matrix4<f32>;
+    /// Whether depth goes from 1 at the near plane to 0 far away instead of
+    /// the other way around.
+    fn reversed_z(&self) -> bool {
+        false
     }
 }
 
 #[derive(Debug)]
-pub struct Projection {
+pub struct Perspective {
     aspect: f32,
     fovy: Rad<f32>,
     znear: f32,
     zfar: f32,
 }
 
-impl Projection {
+impl Perspective {
     pub fn new<F: Into<Rad<f32>>>(width: u32, height: u32, fovy: F, znear: f32, zfar: f32) -> Self {
         Self {
             aspect: width as f32 / height as f32,
@@ -57,119 +102,304 @@ impl Projection {
             zfar,
         }
     }
+}
 
-    pub fn resize(&mut self, width: u32, height: u32) {
+impl Projection for Perspective {
+    fn resize(&mut self, width: u32, height: u32) {
         self.aspect = width as f32 / height as f32;
     }
 
-    pub fn proj_mat(&self) -> Matrix4<f32> {
+    fn proj_mat(&self) -> Matrix4<f32> {
         OPENGL_TO_WGPU_MATRIX * perspective(self.fovy, self.aspect, self.znear, self.zfar)
     }
 }
 
+/// Parallel projection of a box `height` units tall, e.g. for top down map
+/// views or shadow maps.
+#[derive(Debug)]
+pub struct Orthographic {
+    aspect: f32,
+    pub height: f32,
+    znear: f32,
+    zfar: f32,
+}
+
+impl Orthographic {
+    pub fn new(width: u32, height: u32, view_height: f32, znear: f32, zfar: f32) -> Self {
+        Self {
+            aspect: width as f32 / height as f32,
+            height: view_height,
+            znear,
+            zfar,
+        }
+    }
+}
+
+impl Projection for Orthographic {
+    fn resize(&mut self, width: u32, height: u32) {
+        self.aspect = width as f32 / height as f32;
+    }
+
+    fn proj_mat(&self) -> Matrix4<f32> {
+        let (half_width, half_height) = (self.height * self.aspect * 0.5, self.height * 0.5);
+        OPENGL_TO_WGPU_MATRIX
+            * ortho(
+                -half_width,
+                half_width,
+                -half_height,
+                half_height,
+                self.znear,
+                self.zfar,
+            )
+    }
+}
+
+/// Perspective without a far plane, with depth reversed: 1 at `znear`
+/// going towards 0 at infinity. Floats are densest near 0, which evens out
+/// precision over distance. Needs a depth buffer cleared to 0 and compared
+/// with `Greater`.
+#[derive(Debug)]
+pub struct InfinitePerspective {
+    aspect: f32,
+    fovy: Rad<f32>,
+    znear: f32,
+}
+
+impl InfinitePerspective {
+    pub fn new<F: Into<Rad<f32>>>(width: u32, height: u32, fovy: F, znear: f32) -> Self {
+        Self {
+            aspect: width as f32 / height as f32,
+            fovy: fovy.into(),
+            znear,
+        }
+    }
+}
+
+impl Projection for InfinitePerspective {
+    fn resize(&mut self, width: u32, height: u32) {
+        self.aspect = width as f32 / height as f32;
+    }
+
+    #[rustfmt::skip]
+    fn proj_mat(&self) -> Matrix4<f32> {
+        let f = 1.0 / (self.fovy.0 * 0.5).tan();
+        // Clip z is always znear and w the distance, so depth is znear / distance
+        Matrix4::new(
+            f / self.aspect, 0.0, 0.0, 0.0,
+            0.0, f, 0.0, 0.0,
+            0.0, 0.0, 0.0, -1.0,
+            0.0, 0.0, self.znear, 0.0,
+        )
+    }
+
+    fn reversed_z(&self) -> bool {
+        true
+    }
+}
+
+/// Any other projection with depth flipped around, 1 at the near plane and 0
+/// at the far one.
+#[derive(Debug)]
+pub struct ReversedZ<P: Projection>(pub P);
+
+impl<P: Projection> Projection for ReversedZ<P> {
+    fn resize(&mut self, width: u32, height: u32) {
+        self.0.resize(width, height);
+    }
+
+    fn proj_mat(&self) -> Matrix4<f32> {
+        REVERSE_Z_MATRIX * self.0.proj_mat()
+    }
+
+    fn reversed_z(&self) -> bool {
+        !self.0.reversed_z()
+    }
+}
+
+/// How the controller moves the camera.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Mode {
+    /// Free flying, WASD with Space and Shift for up and down.
+    Fly,
+    /// Around a target, scroll zooms and WASD moves the target.
+    Orbit,
+    /// Behind a target walking on the ground, on an arm that gets shorter
+    /// instead of going through terrain.
+    Follow,
+    /// First person, walking on the ground.
+    Walk,
+}
+
+impl Mode {
+    pub fn next(self) -> Self {
+        match self {
+            Mode::Fly => Mode::Orbit,
+            Mode::Orbit => Mode::Follow,
+            Mode::Follow => Mode::Walk,
+            Mode::Walk => Mode::Fly,
+        }
+    }
+}
+
+const MIN_DISTANCE: f32 = 2.0;
+const MAX_DISTANCE: f32 = 500.0;
+// Flying speeds scrolling goes between, a zero or negative speed would turn
+// the controls around
+const MIN_SPEED: f32 = 0.5;
+const MAX_SPEED: f32 = 1000.0;
+/// How far the follow camera stays above the ground.
+const ARM_CLEARANCE: f32 = 0.5;
+/// How fast a shortened arm grows back, per second.
+const ARM_STIFFNESS: f32 = 4.0;
+
 #[derive(Debug)]
 pub struct Controller {
-    amount_right: f32,
-    amount_left: f32,
-    amount_forward: f32,
-    amount_backward: f32,
-    amount_up: f32,
-    amount_down: f32,
+    pub mode: Mode,
+    /// What orbit and follow look at, follow's is on the ground.
+    pub target: Point3<f32>,
+    /// From target to camera in orbit and follow, changed by scrolling.
+    pub distance: f32,
+    /// Of the walking camera and of where follow looks.
+    pub eye_height: f32,
+    // Current length of the follow arm, up to `distance`
+    arm_length: f32,
+    // -1 to 1, from `input::Axis`
+    move_right: f32,
+    move_forward: f32,
+    move_up: f32,
+    look_right: f32,
+    look_up: f32,
+    // Mouse movement in pixels not turned into rotation yet
     rotate_horizontal: f32,
     rotate_vertical: f32,
     scroll: f32,
+    velocity: Vector3<f32>,
     speed: f32,
-    sensitivity: f32,
+    /// Radians per pixel of mouse movement.
+    pub sensitivity: f32,
+    /// Radians per second with a stick pushed all the way.
+    pub stick_speed: f32,
+    /// Seconds the look takes to catch up with the mouse, 0 turns right away.
+    pub smoothing: f32,
+    /// Extra sensitivity per pixel per second of mouse speed, so fast flicks
+    /// turn further than slow moves over the same distance.
+    pub acceleration: f32,
+    /// How fast movement reaches its target velocity and stops again, per
+    /// second. Infinity moves and stops instantly.
+    pub damping: f32,
 }
 
 impl Controller {
     pub fn new(speed: f32, sensitivity: f32) -> Self {
         Self {
-            amount_right: 0.0,
-            amount_left: 0.0,
-            amount_forward: 0.0,
-            amount_backward: 0.0,
-            amount_up: 0.0,
-            amount_down: 0.0,
+            mode: Mode::Fly,
+            target: Point3::origin(),
+            distance: 20.0,
+            eye_height: 1.7,
+            arm_length: 20.0,
+            move_right: 0.0,
+            move_forward: 0.0,
+            move_up: 0.0,
+            look_right: 0.0,
+            look_up: 0.0,
             rotate_horizontal: 0.0,
             rotate_vertical: 0.0,
             scroll: 0.0,
+            velocity: Vector3::zero(),
             speed,
             sensitivity,
+            stick_speed: 2.5,
+            smoothing: 0.0,
+            acceleration: 0.0,
+            damping: 10.0,
         }
     }
 
-    pub fn process_keyboard(&mut self, key: VirtualKeyCode, state: ElementState) -> bool {
-        let amount = if state == ElementState::Pressed {
-            1.0
-        } else {
-            0.0
+    /// Switches to `mode`, picking a target in front of `camera` so the view
+    /// doesn't jump far.
+    pub fn set_mode(&mut self, mode: Mode, camera: &Camera, ground: impl Fn(f32, f32) -> f32) {
+        self.mode = mode;
+        let target = camera.pos + camera.direction() * self.distance;
+        self.target = match mode {
+            Mode::Orbit => target,
+            _ => Point3::new(target.x, ground(target.x, target.z), target.z),
         };
-        match key {
-            VirtualKeyCode::W | VirtualKeyCode::Up => {
-                self.amount_forward = amount;
-                true
-            }
-            VirtualKeyCode::A | VirtualKeyCode::Left => {
-                self.amount_left = amount;
-                true
-            }
-            VirtualKeyCode::S | VirtualKeyCode::Down => {
-                self.amount_backward = amount;
-                true
-            }
-            VirtualKeyCode::D | VirtualKeyCode::Right => {
-                self.amount_right = amount;
-                true
-            }
-            VirtualKeyCode::Space => {
-                self.amount_up = amount;
-                true
-            }
-            VirtualKeyCode::LShift => {
-                self.amount_down = amount;
-                true
-            }
-            _ => false,
-        }
+        self.arm_length = self.distance;
+        self.velocity = Vector3::zero();
+    }
+
+    /// Takes the movement and look axes, once per frame before
+    /// `update_camera`.
+    pub fn process_input(&mut self, input: &input::Input) {
+        self.move_right = input.axis(input::Axis::MoveRight);
+        self.move_forward = input.axis(input::Axis::MoveForward);
+        self.move_up = input.axis(input::Axis::MoveUp);
+        self.look_right = input.axis(input::Axis::LookRight);
+        self.look_up = input.axis(input::Axis::LookUp);
     }
 
+    /// Adds up every event until the next `update_camera`.
     pub fn process_mouse(&mut self, dx: f64, dy: f64) {
-        self.rotate_horizontal = dx as f32;
-        self.rotate_vertical = dy as f32;
+        self.rotate_horizontal += dx as f32;
+        self.rotate_vertical += dy as f32;
     }
 
+    /// Adds up every event until the next `update_camera` too.
     pub fn process_scroll(&mut self, delta: &MouseScrollDelta) {
-        self.scroll = -match delta {
+        self.scroll -= match delta {
             MouseScrollDelta::LineDelta(_, scroll) => scroll * 10.0,
             MouseScrollDelta::PixelDelta(PhysicalPosition { y: scroll, .. }) => *scroll as f32,
         };
-
-        self.speed -= self.scroll;
     }
 
-    pub fn update_camera(&mut self, camera: &mut Camera, dt: std::time::Duration) {
+    /// `ground` is the height of the terrain at an x and z, used by the modes
+    /// that stay on or above it.
+    pub fn update_camera(
+        &mut self,
+        camera: &mut Camera,
+        dt: std::time::Duration,
+        ground: impl Fn(f32, f32) -> f32,
+    ) {
         let dt = dt.as_secs_f32();
 
         let (yaw_sin, yaw_cos) = camera.yaw.0.sin_cos();
         let forward = Vector3::new(yaw_cos, 0.0, yaw_sin).normalize();
         let right = Vector3::new(-yaw_sin, 0.0, yaw_cos).normalize();
 
-        let mut displacement = forward * (self.amount_forward - self.amount_backward)
-            + right * (self.amount_right - self.amount_left)
-            + Vector3::unit_y() * (self.amount_up - self.amount_down);
+        let horizontal = forward * self.move_forward + right * self.move_right;
+        let vertical = self.move_up;
 
-        if !displacement.is_zero() {
-            displacement = displacement.normalize();
+        // Scrolling sets the speed while flying and zooms otherwise, a
+        // factor per notch so it feels the same near and far, slow and fast
+        let factor = 1.01f32.powf(self.scroll);
+        if self.mode == Mode::Fly {
+            self.speed = (self.speed / factor).clamp(MIN_SPEED, MAX_SPEED);
+        } else {
+            self.distance = (self.distance * factor).clamp(MIN_DISTANCE, MAX_DISTANCE);
         }
-
-        camera.pos += displacement * dt * self.speed;
-
-        // TODO: Scroll zoom stuff here
         self.scroll = 0.0;
 
-        camera.yaw += Rad(self.rotate_horizontal) * self.sensitivity * dt;
-        camera.pitch += Rad(-self.rotate_vertical) * self.sensitivity * dt;
+        // Mouse movement is a distance already, no dt. Smoothing takes the
+        // same share of what's left every second, whatever the frame rate.
+        let share = if self.smoothing > 0.0 {
+            1.0 - (-dt / self.smoothing).exp()
+        } else {
+            1.0
+        };
+        let (dx, dy) = (self.rotate_horizontal * share, self.rotate_vertical * share);
+        self.rotate_horizontal -= dx;
+        self.rotate_vertical -= dy;
+        let mouse_speed = if dt > 0.0 {
+            (dx * dx + dy * dy).sqrt() / dt
+        } else {
+            0.0
+        };
+        let sensitivity = self.sensitivity * (1.0 + self.acceleration * mouse_speed);
+        camera.yaw += Rad(dx) * sensitivity;
+        camera.pitch += Rad(-dy) * sensitivity;
+        // Sticks are a rate instead
+        camera.yaw += Rad(self.look_right * self.stick_speed * dt);
+        camera.pitch += Rad(self.look_up * self.stick_speed * dt);
 
         const SAFE_FRAC_PI_2: f32 = FRAC_PI_2 - 0.0001;
         if camera.pitch < -Rad(SAFE_FRAC_PI_2) {
@@ -178,15 +408,195 @@ impl Controller {
             camera.pitch = Rad(SAFE_FRAC_PI_2);
         }
 
+        // Walking modes stay on the ground, the others fly up and down
+        let mut direction = match self.mode {
+            Mode::Fly | Mode::Orbit => horizontal + Vector3::unit_y() * vertical,
+            Mode::Follow | Mode::Walk => horizontal,
+        };
+        if !direction.is_zero() {
+            direction = direction.normalize();
+        }
+        let blend = 1.0 - (-self.damping * dt).exp();
+        self.velocity += (direction * self.speed - self.velocity) * blend;
+        let step = self.velocity * dt;
+
+        match self.mode {
+            Mode::Fly => camera.pos += step,
+            Mode::Orbit => {
+                self.target += step;
+                camera.pos = self.target - camera.direction() * self.distance;
+            }
+            Mode::Follow => {
+                self.target += step;
+                self.target.y = ground(self.target.x, self.target.z);
+
+                let pivot = self.target + Vector3::unit_y() * self.eye_height;
+                let back = -camera.direction();
+                let reach = arm_reach(pivot, back, self.distance, &ground);
+                // Pulled in right away, let out slowly like a spring
+                self.arm_length = if reach < self.arm_length {
+                    reach
+                } else {
+                    self.arm_length
+                        + (reach - self.arm_length) * (1.0 - (-ARM_STIFFNESS * dt).exp())
+                };
+                camera.pos = pivot + back * self.arm_length;
+            }
+            Mode::Walk => {
+                camera.pos += step;
+                camera.pos.y = ground(camera.pos.x, camera.pos.z) + self.eye_height;
+            }
+        }
+
         //print!("\x1B[2J\x1B[1;1H");
         //println!(
-        //    "al: {:?} ar: {:?} af: {:?} ab: {:?} au: {:?} ad: {:?} rv: {:?} rh: {:?}",
-        //    self.amount_left, self.amount_right,
-        //    self.amount_forward, self.amount_backward,
-        //    self.amount_up, self.amount_down,
+        //    "mr: {:?} mf: {:?} mu: {:?} rv: {:?} rh: {:?}",
+        //    self.move_right, self.move_forward, self.move_up,
         //    self.rotate_horizontal, self.rotate_vertical,
         //);
-        self.rotate_horizontal = 0.0;
-        self.rotate_vertical = 0.0;
+    }
+}
+
+/// How far along `direction` from `pivot` the arm gets, up to `length`,
+/// before coming closer than `ARM_CLEARANCE` to the ground.
+fn arm_reach(
+    pivot: Point3<f32>,
+    direction: Vector3<f32>,
+    length: f32,
+    ground: impl Fn(f32, f32) -> f32,
+) -> f32 {
+    const STEPS: u32 = 32;
+    for i in 1..=STEPS {
+        let reach = length * i as f32 / STEPS as f32;
+        let point = pivot + direction * reach;
+        if point.y < ground(point.x, point.z) + ARM_CLEARANCE {
+            return length * (i - 1) as f32 / STEPS as f32;
+        }
+    }
+    length
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input;
+    use std::time::Duration;
+
+    fn flat(_: f32, _: f32) -> f32 {
+        0.0
+    }
+
+    /// Moves the mouse at `pixels_per_second` for a second at `rate` frames
+    /// per second, then lets the look settle.
+    fn look(controller: &mut Controller, rate: u32, pixels_per_second: (f64, f64)) -> Camera {
+        let mut camera = Camera::new((0.0, 0.0, 0.0), Rad(0.0), Rad(0.0));
+        let dt = Duration::from_secs_f64(1.0 / rate as f64);
+        for _ in 0..rate {
+            controller.process_mouse(
+                pixels_per_second.0 / rate as f64,
+                pixels_per_second.1 / rate as f64,
+            );
+            controller.update_camera(&mut camera, dt, flat);
+        }
+        for _ in 0..rate * 5 {
+            controller.update_camera(&mut camera, dt, flat);
+        }
+        camera
+    }
+
+    #[test]
+    fn mouse_look_is_frame_rate_independent() {
+        for &acceleration in [0.0, 0.01].iter() {
+            let mut slow = Controller::new(10.0, 0.004);
+            slow.acceleration = acceleration;
+            let mut fast = Controller::new(10.0, 0.004);
+            fast.acceleration = acceleration;
+
+            let a = look(&mut slow, 30, (300.0, -100.0));
+            let b = look(&mut fast, 144, (300.0, -100.0));
+            assert!((a.yaw.0 - b.yaw.0).abs() < 1e-4, "{:?} {:?}", a.yaw, b.yaw);
+            assert!(
+                (a.pitch.0 - b.pitch.0).abs() < 1e-4,
+                "{:?} {:?}",
+                a.pitch,
+                b.pitch
+            );
+        }
+    }
+
+    #[test]
+    fn smoothing_ends_up_in_the_same_place() {
+        let mut sharp = Controller::new(10.0, 0.004);
+        let mut smooth = Controller::new(10.0, 0.004);
+        smooth.smoothing = 0.1;
+
+        let a = look(&mut sharp, 60, (200.0, 50.0));
+        let b = look(&mut smooth, 60, (200.0, 50.0));
+        assert!((a.yaw.0 - b.yaw.0).abs() < 1e-4, "{:?} {:?}", a.yaw, b.yaw);
+        assert!(
+            (a.pitch.0 - b.pitch.0).abs() < 1e-4,
+            "{:?} {:?}",
+            a.pitch,
+            b.pitch
+        );
+    }
+
+    #[test]
+    fn damping_stops_movement() {
+        let mut input = input::Input::new(input::Bindings::defaults());
+        let forward = input::Button::Key(winit::event::VirtualKeyCode::W);
+        let mut controller = Controller::new(10.0, 0.004);
+        let mut camera = Camera::new((0.0, 0.0, 0.0), Rad(0.0), Rad(0.0));
+        let dt = Duration::from_secs_f64(1.0 / 60.0);
+
+        input.process(forward, true);
+        for _ in 0..60 {
+            controller.process_input(&input);
+            controller.update_camera(&mut camera, dt, flat);
+        }
+        assert!(controller.velocity.magnitude() > 9.0);
+
+        input.process(forward, false);
+        let mut last = controller.velocity.magnitude();
+        for _ in 0..120 {
+            controller.process_input(&input);
+            controller.update_camera(&mut camera, dt, flat);
+            let speed = controller.velocity.magnitude();
+            assert!(speed <= last);
+            last = speed;
+        }
+        assert!(last < 1e-3, "{}", last);
+    }
+
+    #[test]
+    fn scroll_events_add_up() {
+        let notch = MouseScrollDelta::LineDelta(0.0, 1.0);
+        let mut once = Controller::new(10.0, 0.004);
+        let mut twice = Controller::new(10.0, 0.004);
+        let mut camera = Camera::new((0.0, 0.0, 0.0), Rad(0.0), Rad(0.0));
+        let dt = Duration::from_secs_f64(1.0 / 60.0);
+
+        once.process_scroll(&notch);
+        once.update_camera(&mut camera, dt, flat);
+        twice.process_scroll(&notch);
+        twice.process_scroll(&notch);
+        twice.update_camera(&mut camera, dt, flat);
+        assert!(twice.speed > once.speed && once.speed > 10.0);
+    }
+
+    #[test]
+    fn zoom_stays_in_front_of_the_target() {
+        let mut controller = Controller::new(10.0, 0.004);
+        let mut camera = Camera::new((0.0, 0.0, 0.0), Rad(0.0), Rad(0.0));
+        controller.set_mode(Mode::Orbit, &camera, flat);
+        let dt = Duration::from_secs_f64(1.0 / 60.0);
+
+        let pixels = |y| MouseScrollDelta::PixelDelta(PhysicalPosition::new(0.0, y));
+        controller.process_scroll(&pixels(5000.0));
+        controller.update_camera(&mut camera, dt, flat);
+        assert_eq!(controller.distance, MIN_DISTANCE);
+        controller.process_scroll(&pixels(-5000.0));
+        controller.update_camera(&mut camera, dt, flat);
+        assert_eq!(controller.distance, MAX_DISTANCE);
     }
 }
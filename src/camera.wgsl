@@ -0,0 +1,22 @@
+[[block]]
+struct CameraUniform {
+    view_pos: vec4<f32>;
+    view_proj: mat4x4<f32>;
+    inv_view_proj: mat4x4<f32>;
+    // Only used by the CLIP_PLANE variant of shader.wgsl, fragments with
+    // dot(clip_plane, position) < 0 are discarded
+    clip_plane: vec4<f32>;
+};
+
+// Binding 1 of the camera group, see atmosphere.wgsl
+[[block]]
+struct AtmosphereUniform {
+    // Direction towards the sun, intensity
+    sun: vec4<f32>;
+    // Color of the fog without the sky, mode (0 off, 1 linear, 2 exponential)
+    fog_color: vec4<f32>;
+    // Linear start and end, exponential density and height falloff
+    fog: vec4<f32>;
+    // Sky on, meters per world unit, Mie anisotropy
+    scattering: vec4<f32>;
+};
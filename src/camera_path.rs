@@ -0,0 +1,299 @@
+use anyhow::*;
+use cgmath::{Deg, Point3, Rad};
+use std::fmt::Write as _;
+use std::path::Path;
+use std::time::Duration;
+
+use crate::camera;
+
+/// Speed profile between two keyframes.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Easing {
+    Linear,
+    EaseIn,
+    EaseOut,
+    EaseInOut,
+}
+
+impl Easing {
+    fn apply(self, t: f32) -> f32 {
+        match self {
+            Easing::Linear => t,
+            Easing::EaseIn => t * t,
+            Easing::EaseOut => t * (2.0 - t),
+            Easing::EaseInOut => t * t * (3.0 - 2.0 * t),
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Easing::Linear => "linear",
+            Easing::EaseIn => "ease-in",
+            Easing::EaseOut => "ease-out",
+            Easing::EaseInOut => "ease-in-out",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        [
+            Easing::Linear,
+            Easing::EaseIn,
+            Easing::EaseOut,
+            Easing::EaseInOut,
+        ]
+        .iter()
+        .copied()
+        .find(|easing| easing.name() == name)
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct Keyframe {
+    /// Seconds from the start of the path.
+    pub time: f32,
+    pub pos: Point3<f32>,
+    pub yaw: Rad<f32>,
+    pub pitch: Rad<f32>,
+    /// How to get from this keyframe to the next.
+    pub easing: Easing,
+}
+
+impl Keyframe {
+    fn values(&self) -> [f32; 5] {
+        [self.pos.x, self.pos.y, self.pos.z, self.yaw.0, self.pitch.0]
+    }
+}
+
+/// Camera poses over time, interpolated with a Catmull-Rom spline. Saved as
+/// text, one keyframe per line:
+///
+/// ```text
+/// # time x y z yaw pitch [easing]
+/// 0.0 0 5 10 -90 -20
+/// 4.0 50 30 50 -45 -30 ease-in-out
+/// ```
+///
+/// Time is in seconds and angles in degrees. Easing is one of `linear` (the
+/// default), `ease-in`, `ease-out` and `ease-in-out` and applies until the
+/// next keyframe. `#` starts a comment.
+#[derive(Debug, Default, Clone)]
+pub struct CameraPath {
+    /// Sorted by time.
+    pub keyframes: Vec<Keyframe>,
+}
+
+impl CameraPath {
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut keyframes: Vec<Keyframe> = Vec::new();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            if fields.len() != 6 && fields.len() != 7 {
+                bail!(
+                    "Line {}: expected time x y z yaw pitch [easing], got {:?}",
+                    number + 1,
+                    line
+                );
+            }
+
+            let mut numbers = [0.0; 6];
+            for (value, field) in numbers.iter_mut().zip(&fields) {
+                *value = field
+                    .parse()
+                    .map_err(|_| anyhow!("Line {}: {:?} is not a number", number + 1, field))?;
+            }
+            let easing = match fields.get(6) {
+                Some(name) => Easing::from_name(name)
+                    .ok_or_else(|| anyhow!("Line {}: unknown easing {:?}", number + 1, name))?,
+                None => Easing::Linear,
+            };
+
+            let [time, x, y, z, yaw, pitch] = numbers;
+            if keyframes.last().is_some_and(|last| time <= last.time) {
+                bail!("Line {}: keyframe times have to increase", number + 1);
+            }
+
+            keyframes.push(Keyframe {
+                time,
+                pos: Point3::new(x, y, z),
+                yaw: Deg(yaw).into(),
+                pitch: Deg(pitch).into(),
+                easing,
+            });
+        }
+
+        Ok(Self { keyframes })
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("Reading camera path {}", path.display()))?;
+        Self::parse(&text).with_context(|| format!("Parsing camera path {}", path.display()))
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut text = String::from("# time x y z yaw pitch [easing]\n");
+        for key in self.keyframes.iter() {
+            let _ = write!(
+                text,
+                "{:.3} {:.3} {:.3} {:.3} {:.3} {:.3}",
+                key.time,
+                key.pos.x,
+                key.pos.y,
+                key.pos.z,
+                Deg::from(key.yaw).0,
+                Deg::from(key.pitch).0,
+            );
+            if key.easing != Easing::Linear {
+                let _ = write!(text, " {}", key.easing.name());
+            }
+            text.push('\n');
+        }
+        text
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let path = path.as_ref();
+        std::fs::write(path, self.to_text())
+            .with_context(|| format!("Writing camera path {}", path.display()))
+    }
+
+    pub fn duration(&self) -> f32 {
+        self.keyframes.last().map_or(0.0, |key| key.time)
+    }
+
+    /// The pose at `time`, clamped to the ends of the path. Tangents are
+    /// Catmull-Rom's, scaled for keyframes that aren't evenly spaced.
+    pub fn sample(&self, time: f32) -> Option<Keyframe> {
+        let keys = &self.keyframes;
+        let last = keys.len().checked_sub(1)?;
+        let i = keys
+            .iter()
+            .rposition(|key| key.time <= time)
+            .unwrap_or(0)
+            .min(last.saturating_sub(1));
+        if last == 0 || time <= keys[0].time {
+            return Some(keys[0]);
+        }
+        if time >= keys[last].time {
+            return Some(keys[last]);
+        }
+
+        let (a, b) = (&keys[i], &keys[i + 1]);
+        let span = b.time - a.time;
+        let t = a.easing.apply((time - a.time) / span);
+
+        let tangent = |k: usize| -> [f32; 5] {
+            let (before, after) = (&keys[k.saturating_sub(1)], &keys[(k + 1).min(last)]);
+            let (p, q) = (before.values(), after.values());
+            let dt = after.time - before.time;
+            let mut m = [0.0; 5];
+            for c in 0..5 {
+                m[c] = (q[c] - p[c]) / dt;
+            }
+            m
+        };
+
+        // Cubic Hermite basis
+        let (t2, t3) = (t * t, t * t * t);
+        let h00 = 2.0 * t3 - 3.0 * t2 + 1.0;
+        let h10 = t3 - 2.0 * t2 + t;
+        let h01 = -2.0 * t3 + 3.0 * t2;
+        let h11 = t3 - t2;
+
+        let (p0, p1) = (a.values(), b.values());
+        let (m0, m1) = (tangent(i), tangent(i + 1));
+        let mut v = [0.0; 5];
+        for c in 0..5 {
+            v[c] = h00 * p0[c] + h10 * span * m0[c] + h01 * p1[c] + h11 * span * m1[c];
+        }
+
+        Some(Keyframe {
+            time,
+            pos: Point3::new(v[0], v[1], v[2]),
+            yaw: Rad(v[3]),
+            pitch: Rad(v[4]),
+            easing: a.easing,
+        })
+    }
+}
+
+/// Samples the camera into a `CameraPath` every `interval`, the spline fills
+/// in between.
+pub struct Recorder {
+    pub path: CameraPath,
+    pub interval: f32,
+    elapsed: f32,
+    next_sample: f32,
+}
+
+impl Recorder {
+    pub fn new(interval: f32) -> Self {
+        Self {
+            path: CameraPath::default(),
+            interval,
+            elapsed: 0.0,
+            next_sample: 0.0,
+        }
+    }
+
+    pub fn record(&mut self, camera: &camera::Camera, dt: Duration) {
+        if self.elapsed >= self.next_sample {
+            self.path.keyframes.push(Keyframe {
+                time: self.elapsed,
+                pos: camera.pos,
+                yaw: camera.yaw,
+                pitch: camera.pitch,
+                easing: Easing::Linear,
+            });
+            self.next_sample = self.elapsed + self.interval;
+        }
+        self.elapsed += dt.as_secs_f32();
+    }
+}
+
+/// Moves a camera along a `CameraPath`. With a fixed step every frame
+/// advances the same amount no matter how long it took, so a playback
+/// renders the exact same frames every time.
+pub struct Player {
+    pub path: CameraPath,
+    pub fixed_step: Option<Duration>,
+    pub looping: bool,
+    time: f32,
+}
+
+impl Player {
+    pub fn new(path: CameraPath, fixed_step: Option<Duration>) -> Self {
+        Self {
+            path,
+            fixed_step,
+            looping: false,
+            time: 0.0,
+        }
+    }
+
+    /// Poses `camera` for the current time and moves on, false once past the
+    /// end of a path that doesn't loop.
+    pub fn advance(&mut self, camera: &mut camera::Camera, dt: Duration) -> bool {
+        let duration = self.path.duration();
+        if self.time > duration {
+            if !self.looping || duration <= 0.0 {
+                return false;
+            }
+            self.time %= duration;
+        }
+
+        if let Some(pose) = self.path.sample(self.time) {
+            camera.pos = pose.pos;
+            camera.yaw = pose.yaw;
+            camera.pitch = pose.pitch;
+        }
+        self.time += self.fixed_step.unwrap_or(dt).as_secs_f32();
+        true
+    }
+}
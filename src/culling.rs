@@ -0,0 +1,172 @@
+use cgmath::*;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Aabb {
+    pub min: Point3<f32>,
+    pub max: Point3<f32>,
+}
+
+impl Aabb {
+    pub fn center(&self) -> Point3<f32> {
+        self.min.midpoint(self.max)
+    }
+
+    /// The box around this one after `transform`.
+    pub fn transformed(&self, transform: Matrix4<f32>) -> Aabb {
+        let mut min = Point3::new(f32::MAX, f32::MAX, f32::MAX);
+        let mut max = Point3::new(f32::MIN, f32::MIN, f32::MIN);
+        for i in 0..8 {
+            let corner = Point3::new(
+                if i & 1 == 0 { self.min.x } else { self.max.x },
+                if i & 2 == 0 { self.min.y } else { self.max.y },
+                if i & 4 == 0 { self.min.z } else { self.max.z },
+            );
+            let corner = transform.transform_point(corner);
+            min = Point3::new(
+                min.x.min(corner.x),
+                min.y.min(corner.y),
+                min.z.min(corner.z),
+            );
+            max = Point3::new(
+                max.x.max(corner.x),
+                max.y.max(corner.y),
+                max.z.max(corner.z),
+            );
+        }
+        Aabb { min, max }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Sphere {
+    pub center: Point3<f32>,
+    pub radius: f32,
+}
+
+/// A box and a sphere around the same points. The sphere is the cheap first
+/// test, the box the tighter second one.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Bounds {
+    pub aabb: Aabb,
+    pub sphere: Sphere,
+}
+
+impl Bounds {
+    /// Bounds of `points`, all zero if there are none.
+    pub fn from_points<I: IntoIterator<Item = [f32; 3]> + Clone>(points: I) -> Self {
+        let mut min = Point3::new(f32::MAX, f32::MAX, f32::MAX);
+        let mut max = Point3::new(f32::MIN, f32::MIN, f32::MIN);
+        let mut any = false;
+        for point in points.clone() {
+            any = true;
+            min = Point3::new(
+                min.x.min(point[0]),
+                min.y.min(point[1]),
+                min.z.min(point[2]),
+            );
+            max = Point3::new(
+                max.x.max(point[0]),
+                max.y.max(point[1]),
+                max.z.max(point[2]),
+            );
+        }
+
+        if !any {
+            min = Point3::origin();
+            max = Point3::origin();
+        }
+
+        let aabb = Aabb { min, max };
+        let center = aabb.center();
+        let radius = points
+            .into_iter()
+            .map(|point| Point3::from(point).distance2(center))
+            .fold(0.0, f32::max)
+            .sqrt();
+
+        Self {
+            aabb,
+            sphere: Sphere { center, radius },
+        }
+    }
+}
+
+/// The six planes of a view projection matrix, pointing inwards, as
+/// (normal, distance) in xyz and w.
+#[derive(Debug, Copy, Clone)]
+pub struct Frustum {
+    planes: [Vector4<f32>; 6],
+}
+
+impl Frustum {
+    /// Gribb/Hartmann plane extraction, for wgpu's 0 to 1 clip space depth.
+    pub fn from_matrix(view_proj: Matrix4<f32>) -> Self {
+        let row = |i: usize| {
+            Vector4::new(
+                view_proj.x[i],
+                view_proj.y[i],
+                view_proj.z[i],
+                view_proj.w[i],
+            )
+        };
+        let (x, y, z, w) = (row(0), row(1), row(2), row(3));
+
+        let mut planes = [w + x, w - x, w + y, w - y, z, w - z];
+        for plane in planes.iter_mut() {
+            let length = plane.truncate().magnitude();
+            // An infinite far plane, nothing is behind it
+            *plane = if length > f32::EPSILON {
+                *plane / length
+            } else {
+                Vector4::new(0.0, 0.0, 0.0, 1.0)
+            };
+        }
+
+        Self { planes }
+    }
+
+    fn distance(plane: &Vector4<f32>, point: Point3<f32>) -> f32 {
+        plane.truncate().dot(point.to_vec()) + plane.w
+    }
+
+    pub fn intersects_sphere(&self, sphere: &Sphere) -> bool {
+        self.planes
+            .iter()
+            .all(|plane| Self::distance(plane, sphere.center) >= -sphere.radius)
+    }
+
+    pub fn intersects_aabb(&self, aabb: &Aabb) -> bool {
+        self.planes.iter().all(|plane| {
+            // The corner furthest along the plane normal
+            let corner = Point3::new(
+                if plane.x >= 0.0 {
+                    aabb.max.x
+                } else {
+                    aabb.min.x
+                },
+                if plane.y >= 0.0 {
+                    aabb.max.y
+                } else {
+                    aabb.min.y
+                },
+                if plane.z >= 0.0 {
+                    aabb.max.z
+                } else {
+                    aabb.min.z
+                },
+            );
+            Self::distance(plane, corner) >= 0.0
+        })
+    }
+
+    pub fn intersects(&self, bounds: &Bounds) -> bool {
+        self.intersects_sphere(&bounds.sphere) && self.intersects_aabb(&bounds.aabb)
+    }
+}
+
+/// How many submeshes were drawn and skipped in a frame.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct Stats {
+    pub drawn: u32,
+    pub culled: u32,
+}
@@ -0,0 +1,187 @@
+use cgmath::*;
+
+use crate::mesh;
+
+pub const RED: [f32; 4] = [1.0, 0.0, 0.0, 1.0];
+pub const GREEN: [f32; 4] = [0.0, 1.0, 0.0, 1.0];
+pub const BLUE: [f32; 4] = [0.0, 0.0, 1.0, 1.0];
+pub const YELLOW: [f32; 4] = [1.0, 1.0, 0.0, 1.0];
+
+const CIRCLE_SEGMENTS: usize = 32;
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct LineVertex {
+    pub position: [f32; 3],
+    pub color: [f32; 4],
+}
+
+impl LineVertex {
+    pub fn layout<'a>() -> wgpu::VertexBufferLayout<'a> {
+        const ATTRIBUTES: [wgpu::VertexAttribute; 2] = wgpu::vertex_attr_array![
+            0 => Float32x3, // Position
+            1 => Float32x4  // Color
+        ];
+
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<LineVertex>() as u64,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: &ATTRIBUTES,
+        }
+    }
+}
+
+/// Immediate mode debug drawing. Everything added during a frame goes into
+/// one vertex buffer on `upload` and is drawn as a line list after the scene,
+/// then forgotten.
+pub struct DebugDraw {
+    vertices: Vec<LineVertex>,
+    buffer: wgpu::Buffer,
+    // In vertices
+    capacity: usize,
+    count: u32,
+}
+
+impl DebugDraw {
+    pub fn new(device: &wgpu::Device) -> Self {
+        let capacity = 1024;
+        Self {
+            vertices: Vec::new(),
+            buffer: Self::create_buffer(device, capacity),
+            capacity,
+            count: 0,
+        }
+    }
+
+    fn create_buffer(device: &wgpu::Device, capacity: usize) -> wgpu::Buffer {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Debug Line Buffer"),
+            size: (capacity * std::mem::size_of::<LineVertex>()) as u64,
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        })
+    }
+
+    pub fn line(&mut self, a: Point3<f32>, b: Point3<f32>, color: [f32; 4]) {
+        self.vertices.push(LineVertex {
+            position: a.into(),
+            color,
+        });
+        self.vertices.push(LineVertex {
+            position: b.into(),
+            color,
+        });
+    }
+
+    /// The 12 edges of the box with corners `corners[i]`, where bit 0 of `i`
+    /// picks x, bit 1 y and bit 2 z.
+    fn box_edges(&mut self, corners: &[Point3<f32>; 8], color: [f32; 4]) {
+        for i in 0..8 {
+            for bit in [1, 2, 4] {
+                if i & bit == 0 {
+                    self.line(corners[i], corners[i | bit], color);
+                }
+            }
+        }
+    }
+
+    /// Axis aligned box.
+    pub fn aabb(&mut self, min: Point3<f32>, max: Point3<f32>, color: [f32; 4]) {
+        let mut corners = [min; 8];
+        for (i, corner) in corners.iter_mut().enumerate() {
+            *corner = Point3::new(
+                if i & 1 == 0 { min.x } else { max.x },
+                if i & 2 == 0 { min.y } else { max.y },
+                if i & 4 == 0 { min.z } else { max.z },
+            );
+        }
+        self.box_edges(&corners, color);
+    }
+
+    /// Three circles, one around each axis.
+    pub fn sphere(&mut self, center: Point3<f32>, radius: f32, color: [f32; 4]) {
+        let point = |axis: usize, angle: f32| {
+            let (sin, cos) = angle.sin_cos();
+            let offset = match axis {
+                0 => Vector3::new(0.0, cos, sin),
+                1 => Vector3::new(cos, 0.0, sin),
+                _ => Vector3::new(cos, sin, 0.0),
+            };
+            center + offset * radius
+        };
+
+        let step = std::f32::consts::TAU / CIRCLE_SEGMENTS as f32;
+        for axis in 0..3 {
+            for i in 0..CIRCLE_SEGMENTS {
+                let a = point(axis, i as f32 * step);
+                let b = point(axis, (i + 1) as f32 * step);
+                self.line(a, b, color);
+            }
+        }
+    }
+
+    /// X, Y and Z in red, green and blue.
+    pub fn axes(&mut self, origin: Point3<f32>, size: f32) {
+        self.line(origin, origin + Vector3::unit_x() * size, RED);
+        self.line(origin, origin + Vector3::unit_y() * size, GREEN);
+        self.line(origin, origin + Vector3::unit_z() * size, BLUE);
+    }
+
+    /// The volume a camera with this view projection matrix sees.
+    pub fn frustum(&mut self, view_proj: Matrix4<f32>, color: [f32; 4]) {
+        let inverse = match view_proj.invert() {
+            Some(inverse) => inverse,
+            None => return,
+        };
+
+        // wgpu clip space, z goes from 0 to 1
+        let mut corners = [Point3::origin(); 8];
+        for (i, corner) in corners.iter_mut().enumerate() {
+            let mut ndc = Vector4::new(
+                if i & 1 == 0 { -1.0 } else { 1.0 },
+                if i & 2 == 0 { -1.0 } else { 1.0 },
+                if i & 4 == 0 { 0.0 } else { 1.0 },
+                1.0,
+            );
+            let mut world = inverse * ndc;
+            if world.w.abs() < 1e-6 {
+                // An infinite far plane, cut off a little closer
+                ndc.z = (ndc.z - 0.5) * 0.9998 + 0.5;
+                world = inverse * ndc;
+            }
+            *corner = Point3::from_homogeneous(world);
+        }
+        self.box_edges(&corners, color);
+    }
+
+    /// A line of `length` along the normal of every vertex.
+    pub fn normals(&mut self, descriptor: &mesh::Descriptor, length: f32, color: [f32; 4]) {
+        for (vertex, normal) in descriptor.vertices.iter().zip(descriptor.normals.iter()) {
+            let start = Point3::from(*vertex);
+            self.line(start, start + Vector3::from(*normal) * length, color);
+        }
+    }
+
+    /// Sends this frame's lines to the GPU, growing the buffer if needed.
+    pub fn upload(&mut self, device: &wgpu::Device, queue: &wgpu::Queue) {
+        if self.vertices.len() > self.capacity {
+            self.capacity = self.vertices.len().next_power_of_two();
+            self.buffer = Self::create_buffer(device, self.capacity);
+        }
+
+        queue.write_buffer(&self.buffer, 0, bytemuck::cast_slice(&self.vertices));
+        self.count = self.vertices.len() as u32;
+        self.vertices.clear();
+    }
+
+    /// Draws what was last uploaded. The pipeline and camera bind group
+    /// (group 0) are up to the caller.
+    pub fn draw<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) {
+        if self.count == 0 {
+            return;
+        }
+
+        render_pass.set_vertex_buffer(0, self.buffer.slice(..));
+        render_pass.draw(0..self.count, 0..1);
+    }
+}
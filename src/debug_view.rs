@@ -0,0 +1,106 @@
+use crate::input;
+use crate::pipeline;
+
+/// What the scene pass draws instead of the shaded scene, picked with F1-F6.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum DebugView {
+    Shaded,
+    Wireframe,
+    Normals,
+    Uvs,
+    Depth,
+    /// Every fragment adds a bit of color, the brighter the more overdraw.
+    Overdraw,
+}
+
+impl DebugView {
+    pub fn from_action(action: input::Action) -> Option<Self> {
+        match action {
+            input::Action::DebugShaded => Some(DebugView::Shaded),
+            input::Action::DebugWireframe => Some(DebugView::Wireframe),
+            input::Action::DebugNormals => Some(DebugView::Normals),
+            input::Action::DebugUvs => Some(DebugView::Uvs),
+            input::Action::DebugDepth => Some(DebugView::Depth),
+            input::Action::DebugOverdraw => Some(DebugView::Overdraw),
+            _ => None,
+        }
+    }
+
+    fn define(&self) -> Option<&'static str> {
+        match self {
+            DebugView::Shaded => None,
+            DebugView::Wireframe => Some("DEBUG_WIREFRAME"),
+            DebugView::Normals => Some("DEBUG_NORMALS"),
+            DebugView::Uvs => Some("DEBUG_UVS"),
+            DebugView::Depth => Some("DEBUG_DEPTH"),
+            DebugView::Overdraw => Some("DEBUG_OVERDRAW"),
+        }
+    }
+
+    /// Wireframes without `PolygonMode::Line` are drawn with wireframe.wgsl,
+    /// which pulls vertices from `GeometryPool::storage` and needs
+    /// `GeometryPool::draw_indirect` without `indexed`.
+    pub fn barycentric(&self, line_mode: bool) -> bool {
+        *self == DebugView::Wireframe && !line_mode
+    }
+
+    /// Turns the pipeline of the shaded scene into the one for this view.
+    /// `line_mode` is whether the device has `NON_FILL_POLYGON_MODE`.
+    pub fn pipeline(
+        &self,
+        shaded: pipeline::PipelineBuilder,
+        line_mode: bool,
+    ) -> pipeline::PipelineBuilder {
+        let define = match self.define() {
+            Some(define) => define,
+            None => return shaded,
+        };
+
+        if self.barycentric(line_mode) {
+            let builder = pipeline::PipelineBuilder::new(
+                "Wireframe Pipeline",
+                "wireframe",
+                "wireframe.wgsl",
+                shaded.color_format,
+            )
+            .cull_mode(None)
+            .multisample(shaded.sample_count, false);
+
+            return match shaded.depth {
+                Some(depth) => builder.depth(depth.format, depth.write, depth.compare),
+                None => builder,
+            };
+        }
+
+        let defines = shaded.defines.clone().with("DEBUG_VIEW").with(define);
+        let builder = shaded.defines(defines);
+
+        match self {
+            DebugView::Wireframe => builder
+                .polygon_mode(wgpu::PolygonMode::Line)
+                .cull_mode(None),
+            DebugView::Overdraw => {
+                let additive = wgpu::BlendComponent {
+                    src_factor: wgpu::BlendFactor::One,
+                    dst_factor: wgpu::BlendFactor::One,
+                    operation: wgpu::BlendOperation::Add,
+                };
+                let builder = builder
+                    .blend(Some(wgpu::BlendState {
+                        color: additive,
+                        alpha: additive,
+                    }))
+                    .cull_mode(None);
+
+                // Count hidden fragments too
+                match builder.depth {
+                    Some(depth) => {
+                        builder.depth(depth.format, false, wgpu::CompareFunction::Always)
+                    }
+                    None => builder,
+                }
+            }
+            _ => builder,
+        }
+    }
+}
@@ -0,0 +1,276 @@
+use std::any::{Any, TypeId};
+use std::cell::{Ref, RefCell, RefMut};
+use std::collections::HashMap;
+use std::time::Duration;
+
+/// Something in the world, a handle to its components.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct Entity(u32);
+
+/// Every component of one type, indexed by entity.
+pub struct Storage<C> {
+    entries: Vec<Option<C>>,
+}
+
+impl<C> Storage<C> {
+    fn new() -> Self {
+        Self {
+            entries: Vec::new(),
+        }
+    }
+
+    /// Replaces what `entity` had. Use `World::insert` if `C` may not be
+    /// registered yet.
+    pub fn insert(&mut self, entity: Entity, component: C) {
+        let index = entity.0 as usize;
+        if index >= self.entries.len() {
+            self.entries.resize_with(index + 1, || None);
+        }
+        self.entries[index] = Some(component);
+    }
+
+    fn take(&mut self, entity: Entity) -> Option<C> {
+        self.entries.get_mut(entity.0 as usize)?.take()
+    }
+
+    pub fn get(&self, entity: Entity) -> Option<&C> {
+        self.entries.get(entity.0 as usize)?.as_ref()
+    }
+
+    pub fn get_mut(&mut self, entity: Entity) -> Option<&mut C> {
+        self.entries.get_mut(entity.0 as usize)?.as_mut()
+    }
+
+    pub fn contains(&self, entity: Entity) -> bool {
+        self.get(entity).is_some()
+    }
+
+    /// Every entity with this component, in the order they were spawned.
+    pub fn iter(&self) -> impl Iterator<Item = (Entity, &C)> {
+        self.entries
+            .iter()
+            .enumerate()
+            .filter_map(|(i, entry)| Some((Entity(i as u32), entry.as_ref()?)))
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (Entity, &mut C)> {
+        self.entries
+            .iter_mut()
+            .enumerate()
+            .filter_map(|(i, entry)| Some((Entity(i as u32), entry.as_mut()?)))
+    }
+}
+
+// Lets `World` keep storages of every component type in one map
+trait AnyStorage {
+    fn as_any(&self) -> &dyn Any;
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+}
+
+impl<C: 'static> AnyStorage for Storage<C> {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+/// Entities and their components, any `'static` type is a component.
+///
+/// Queries go through the storages, which are borrowed separately so a
+/// system can write one component while reading others:
+///
+/// ```ignore
+/// let mut transforms = world.write::<Transform>();
+/// let spins = world.read::<Spin>();
+/// for (entity, transform) in transforms.iter_mut() {
+///     if let Some(spin) = spins.get(entity) { ... }
+/// }
+/// ```
+///
+/// Borrowing the same storage mutably twice panics, like a `RefCell`.
+#[derive(Default)]
+pub struct World {
+    entities: u32,
+    storages: HashMap<TypeId, RefCell<Box<dyn AnyStorage>>>,
+}
+
+impl World {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A new entity without components.
+    pub fn spawn(&mut self) -> Entity {
+        self.entities += 1;
+        Entity(self.entities - 1)
+    }
+
+    /// Makes a storage for `C` so it can be queried before any entity has one.
+    pub fn register<C: 'static>(&mut self) {
+        self.storages
+            .entry(TypeId::of::<C>())
+            .or_insert_with(|| RefCell::new(Box::new(Storage::<C>::new())));
+    }
+
+    /// Adds `component` to `entity`, replacing the one it had of that type.
+    pub fn insert<C: 'static>(&mut self, entity: Entity, component: C) {
+        self.register::<C>();
+        self.write::<C>().insert(entity, component);
+    }
+
+    pub fn remove<C: 'static>(&mut self, entity: Entity) -> Option<C> {
+        let storage = self.storages.get_mut(&TypeId::of::<C>())?;
+        storage
+            .get_mut()
+            .as_any_mut()
+            .downcast_mut::<Storage<C>>()
+            .and_then(|storage| storage.take(entity))
+    }
+
+    /// Every `C` in the world, panics if `C` was never registered.
+    pub fn read<C: 'static>(&self) -> Ref<'_, Storage<C>> {
+        Ref::map(self.storage::<C>().borrow(), |storage| {
+            storage.as_any().downcast_ref().unwrap()
+        })
+    }
+
+    pub fn write<C: 'static>(&self) -> RefMut<'_, Storage<C>> {
+        RefMut::map(self.storage::<C>().borrow_mut(), |storage| {
+            storage.as_any_mut().downcast_mut().unwrap()
+        })
+    }
+
+    fn storage<C: 'static>(&self) -> &RefCell<Box<dyn AnyStorage>> {
+        self.storages
+            .get(&TypeId::of::<C>())
+            .unwrap_or_else(|| panic!("{} is not registered", std::any::type_name::<C>()))
+    }
+}
+
+/// What systems get besides the world on every tick.
+pub struct Tick<'a> {
+    /// Always the tick length, see `timestep::FixedTimestep`.
+    pub dt: Duration,
+    /// Height of the ground at x, z.
+    pub ground: &'a dyn Fn(f32, f32) -> f32,
+}
+
+/// Game logic run every tick, e.g. moving everything with a velocity.
+pub trait System {
+    fn run(&mut self, world: &mut World, tick: &Tick);
+}
+
+impl<F: FnMut(&mut World, &Tick)> System for F {
+    fn run(&mut self, world: &mut World, tick: &Tick) {
+        self(world, tick)
+    }
+}
+
+/// Systems run one after the other in the order they were added, so a
+/// system sees everything the ones before it did this tick.
+#[derive(Default)]
+pub struct Schedule {
+    systems: Vec<(&'static str, Box<dyn System>)>,
+}
+
+impl Schedule {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add<S: System + 'static>(&mut self, name: &'static str, system: S) -> &mut Self {
+        self.systems.push((name, Box::new(system)));
+        self
+    }
+
+    /// Names in the order they run.
+    pub fn names(&self) -> impl Iterator<Item = &'static str> + '_ {
+        self.systems.iter().map(|(name, _)| *name)
+    }
+
+    pub fn run(&mut self, world: &mut World, tick: &Tick) {
+        for (_, system) in self.systems.iter_mut() {
+            system.run(world, tick);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, PartialEq)]
+    struct Position(f32);
+    struct Velocity(f32);
+
+    fn tick(world: &mut World, schedule: &mut Schedule) {
+        let tick = Tick {
+            dt: Duration::from_millis(500),
+            ground: &|_, _| 0.0,
+        };
+        schedule.run(world, &tick);
+    }
+
+    fn movement(world: &mut World, tick: &Tick) {
+        let mut positions = world.write::<Position>();
+        let velocities = world.read::<Velocity>();
+        for (entity, velocity) in velocities.iter() {
+            if let Some(position) = positions.get_mut(entity) {
+                position.0 += velocity.0 * tick.dt.as_secs_f32();
+            }
+        }
+    }
+
+    #[test]
+    fn systems_update_entities() {
+        let mut world = World::new();
+        world.register::<Velocity>();
+        let moving = world.spawn();
+        world.insert(moving, Position(1.0));
+        world.insert(moving, Velocity(4.0));
+        let still = world.spawn();
+        world.insert(still, Position(1.0));
+
+        let mut schedule = Schedule::new();
+        schedule.add("movement", movement);
+        tick(&mut world, &mut schedule);
+        tick(&mut world, &mut schedule);
+
+        assert_eq!(world.read::<Position>().get(moving), Some(&Position(5.0)));
+        assert_eq!(world.read::<Position>().get(still), Some(&Position(1.0)));
+        assert_eq!(world.remove::<Velocity>(moving).map(|v| v.0), Some(4.0));
+        assert!(!world.read::<Velocity>().contains(moving));
+    }
+
+    #[test]
+    fn schedule_runs_systems_in_order() {
+        let mut world = World::new();
+        let log = world.spawn();
+        world.insert(log, Vec::<&'static str>::new());
+
+        let record = |name| {
+            move |world: &mut World, _: &Tick| {
+                world
+                    .write::<Vec<&'static str>>()
+                    .get_mut(log)
+                    .unwrap()
+                    .push(name)
+            }
+        };
+        let mut schedule = Schedule::new();
+        schedule
+            .add("b", record("b"))
+            .add("a", record("a"))
+            .add("c", record("c"));
+        assert_eq!(schedule.names().collect::<Vec<_>>(), vec!["b", "a", "c"]);
+
+        tick(&mut world, &mut schedule);
+        assert_eq!(
+            world.read::<Vec<&'static str>>().get(log).unwrap(),
+            &vec!["b", "a", "c"]
+        );
+    }
+}
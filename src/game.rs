@@ -0,0 +1,320 @@
+use cgmath::*;
+
+use crate::camera;
+use crate::ecs::{self, Entity, World};
+use crate::transparency;
+
+/// Where an entity is. Forward is +x like `camera::Camera` at no yaw.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Transform {
+    pub position: Point3<f32>,
+    pub rotation: Quaternion<f32>,
+    pub scale: f32,
+}
+
+impl Transform {
+    pub fn new<P: Into<Point3<f32>>>(position: P) -> Self {
+        Self {
+            position: position.into(),
+            rotation: Quaternion::one(),
+            scale: 1.0,
+        }
+    }
+
+    pub fn matrix(&self) -> Matrix4<f32> {
+        Matrix4::from_translation(self.position.to_vec())
+            * Matrix4::from(self.rotation)
+            * Matrix4::from_scale(self.scale)
+    }
+
+    pub fn forward(&self) -> Vector3<f32> {
+        self.rotation.rotate_vector(Vector3::unit_x())
+    }
+
+    /// Partway from this transform to `other`, `t` from 0 to 1.
+    pub fn lerp(&self, other: &Transform, t: f32) -> Transform {
+        Transform {
+            position: self.position + (other.position - self.position) * t,
+            rotation: self.rotation.nlerp(other.rotation, t),
+            scale: self.scale + (other.scale - self.scale) * t,
+        }
+    }
+}
+
+/// Where the transform was on the tick before, for rendering in between.
+/// Kept up to date for every entity with a transform.
+#[derive(Debug, Copy, Clone)]
+struct Previous(Transform);
+
+/// Drawn as an instance of one of `transparency::Objects::meshes`.
+#[derive(Debug, Copy, Clone)]
+pub struct Mesh {
+    /// Index into `Objects::meshes`.
+    pub mesh: usize,
+    /// Multiplied with the texture, alpha included.
+    pub color: [f32; 4],
+    pub mode: transparency::AlphaMode,
+}
+
+/// Something to look through, along its transform's forward. The one with
+/// `ActiveCamera` is rendered from instead of the free camera.
+#[derive(Debug, Copy, Clone)]
+pub struct Camera;
+
+#[derive(Debug, Copy, Clone)]
+pub struct ActiveCamera;
+
+/// A directional light shining along its transform's forward. The renderer
+/// only has the sun, so the first light is the sun.
+#[derive(Debug, Copy, Clone)]
+pub struct Light {
+    pub intensity: f32,
+}
+
+/// Turns around the y axis at a constant speed.
+#[derive(Debug, Copy, Clone)]
+pub struct Spin(pub Rad<f32>);
+
+/// Circles `center` facing it, tilted down by `pitch`.
+#[derive(Debug, Copy, Clone)]
+pub struct Orbit {
+    pub center: Point3<f32>,
+    pub radius: f32,
+    /// Per second.
+    pub speed: Rad<f32>,
+    pub angle: Rad<f32>,
+    pub pitch: Rad<f32>,
+}
+
+/// Stays this high above the terrain.
+#[derive(Debug, Copy, Clone)]
+pub struct OnGround(pub f32);
+
+/// Storages for every component above, so systems can query them before
+/// anything has one.
+pub fn register(world: &mut World) {
+    world.register::<Transform>();
+    world.register::<Previous>();
+    world.register::<Mesh>();
+    world.register::<Camera>();
+    world.register::<ActiveCamera>();
+    world.register::<Light>();
+    world.register::<Spin>();
+    world.register::<Orbit>();
+    world.register::<OnGround>();
+}
+
+/// The game's systems in the order they run each tick.
+pub fn schedule() -> ecs::Schedule {
+    let mut schedule = ecs::Schedule::new();
+    schedule
+        .add("remember-transforms", remember_transforms)
+        .add("orbit", orbit)
+        .add("spin", spin)
+        // After everything that moves things sideways
+        .add("on-ground", on_ground);
+    schedule
+}
+
+/// Saves the transforms before this tick changes them.
+fn remember_transforms(world: &mut World, _: &ecs::Tick) {
+    let transforms = world.read::<Transform>();
+    let mut previous = world.write::<Previous>();
+    for (entity, transform) in transforms.iter() {
+        if let Some(previous) = previous.get_mut(entity) {
+            previous.0 = *transform;
+        } else {
+            previous.insert(entity, Previous(*transform));
+        }
+    }
+}
+
+fn orbit(world: &mut World, tick: &ecs::Tick) {
+    let mut transforms = world.write::<Transform>();
+    let mut orbits = world.write::<Orbit>();
+    for (entity, orbit) in orbits.iter_mut() {
+        let transform = match transforms.get_mut(entity) {
+            Some(transform) => transform,
+            None => continue,
+        };
+        orbit.angle += orbit.speed * tick.dt.as_secs_f32();
+        let (sin, cos) = orbit.angle.sin_cos();
+        transform.position = orbit.center + Vector3::new(cos, 0.0, sin) * orbit.radius;
+        // Turns forward (+x) towards the center, then down
+        transform.rotation = Quaternion::from_angle_y(Rad::turn_div_2() - orbit.angle)
+            * Quaternion::from_angle_z(-orbit.pitch);
+    }
+}
+
+fn spin(world: &mut World, tick: &ecs::Tick) {
+    let mut transforms = world.write::<Transform>();
+    let spins = world.read::<Spin>();
+    for (entity, spin) in spins.iter() {
+        if let Some(transform) = transforms.get_mut(entity) {
+            let turn = Quaternion::from_angle_y(spin.0 * tick.dt.as_secs_f32());
+            transform.rotation = (turn * transform.rotation).normalize();
+        }
+    }
+}
+
+fn on_ground(world: &mut World, tick: &ecs::Tick) {
+    let mut transforms = world.write::<Transform>();
+    let grounded = world.read::<OnGround>();
+    for (entity, height) in grounded.iter() {
+        if let Some(transform) = transforms.get_mut(entity) {
+            let position = transform.position;
+            transform.position.y = (tick.ground)(position.x, position.z) + height.0;
+        }
+    }
+}
+
+/// Between `entity`'s last two ticks, `alpha` of the way.
+pub fn interpolated(world: &World, entity: Entity, alpha: f32) -> Option<Transform> {
+    let transform = *world.read::<Transform>().get(entity)?;
+    Some(match world.read::<Previous>().get(entity) {
+        Some(previous) => previous.0.lerp(&transform, alpha),
+        None => transform,
+    })
+}
+
+/// Every mesh entity as an object to draw, replacing `objects`.
+pub fn collect_objects(world: &World, alpha: f32, objects: &mut Vec<transparency::Object>) {
+    objects.clear();
+    for (entity, mesh) in world.read::<Mesh>().iter() {
+        if let Some(transform) = interpolated(world, entity, alpha) {
+            objects.push(transparency::Object {
+                mesh: mesh.mesh,
+                transform: transform.matrix(),
+                color: mesh.color,
+                mode: mesh.mode,
+            });
+        }
+    }
+}
+
+/// What the active camera entity sees, if there is one.
+pub fn active_camera(world: &World, alpha: f32) -> Option<camera::Camera> {
+    let (entity, _) = world.read::<ActiveCamera>().iter().next()?;
+    let transform = interpolated(world, entity, alpha)?;
+    let forward = transform.forward();
+    Some(camera::Camera::new(
+        transform.position,
+        Rad(forward.z.atan2(forward.x)),
+        Rad(forward.y.clamp(-1.0, 1.0).asin()),
+    ))
+}
+
+/// A few colored glass panes and foliage cards to show off both alpha modes,
+/// standing on the ground. The panes spin, and a camera circles them.
+/// `glass` and `leaves` index `transparency::Objects::meshes`.
+pub fn spawn_demo_scene(
+    world: &mut World,
+    glass: usize,
+    leaves: usize,
+    ground: impl Fn(f32, f32) -> f32,
+) {
+    let colors = [
+        [1.0, 0.3, 0.3, 0.5],
+        [0.3, 1.0, 0.3, 0.5],
+        [0.3, 0.3, 1.0, 0.5],
+    ];
+    for i in 0..9 {
+        let (x, z) = (20.0 + (i % 3) as f32 * 6.0, 20.0 + (i / 3) as f32 * 6.0);
+        let y = ground(x, z);
+
+        let pane = world.spawn();
+        world.insert(
+            pane,
+            Transform {
+                position: Point3::new(x, y + 3.0, z),
+                rotation: Quaternion::from_angle_y(Deg(i as f32 * 20.0)),
+                scale: 4.0,
+            },
+        );
+        world.insert(
+            pane,
+            Mesh {
+                mesh: glass,
+                color: colors[i % 3],
+                mode: transparency::AlphaMode::Blend,
+            },
+        );
+        world.insert(pane, Spin(Deg(10.0 + i as f32 * 5.0).into()));
+
+        // Two crossed cards, like a bush
+        for angle in [45.0, 135.0] {
+            let card = world.spawn();
+            world.insert(
+                card,
+                Transform {
+                    position: Point3::new(x + 3.0, y + 1.0, z + 3.0),
+                    rotation: Quaternion::from_angle_y(Deg(angle)),
+                    scale: 2.0,
+                },
+            );
+            world.insert(
+                card,
+                Mesh {
+                    mesh: leaves,
+                    color: [1.0, 1.0, 1.0, 1.0],
+                    mode: transparency::AlphaMode::Cutout,
+                },
+            );
+        }
+    }
+
+    let camera = world.spawn();
+    world.insert(camera, Transform::new((0.0, 0.0, 0.0)));
+    world.insert(camera, Camera);
+    world.insert(
+        camera,
+        Orbit {
+            center: Point3::new(26.0, 0.0, 26.0),
+            radius: 30.0,
+            speed: Deg(10.0).into(),
+            angle: Rad(0.0),
+            pitch: Deg(20.0).into(),
+        },
+    );
+    world.insert(camera, OnGround(12.0));
+}
+
+/// Makes the next camera entity active, after the last one goes back to
+/// the free camera and returns None.
+pub fn cycle_camera(world: &mut World) -> Option<Entity> {
+    let cameras: Vec<Entity> = world
+        .read::<Camera>()
+        .iter()
+        .map(|(entity, _)| entity)
+        .collect();
+    let active = cameras
+        .iter()
+        .position(|&entity| world.read::<ActiveCamera>().contains(entity));
+    if let Some(i) = active {
+        world.remove::<ActiveCamera>(cameras[i]);
+    }
+
+    let next = match active {
+        Some(i) => cameras.get(i + 1).copied(),
+        None => cameras.first().copied(),
+    }?;
+    world.insert(next, ActiveCamera);
+    Some(next)
+}
+
+/// A light for the sun, shining from `direction` (towards the sun).
+pub fn spawn_sun(world: &mut World, direction: Vector3<f32>, intensity: f32) -> Entity {
+    let sun = world.spawn();
+    let mut transform = Transform::new((0.0, 0.0, 0.0));
+    transform.rotation = Quaternion::from_arc(Vector3::unit_x(), -direction, None);
+    world.insert(sun, transform);
+    world.insert(sun, Light { intensity });
+    sun
+}
+
+/// The first light's direction towards it and intensity, for the sun.
+pub fn sun(world: &World) -> Option<(Vector3<f32>, f32)> {
+    let (entity, light) = world.read::<Light>().iter().next().map(|(e, l)| (e, *l))?;
+    let transform = world.read::<Transform>().get(entity).copied()?;
+    Some((-transform.forward(), light.intensity))
+}
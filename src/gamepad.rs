@@ -0,0 +1,374 @@
+use anyhow::*;
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use std::time::Duration;
+
+use crate::input::{self, Analog, GamepadButton};
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Event {
+    Button(GamepadButton, bool),
+    /// Raw, before the dead zone.
+    Analog(Analog, f32),
+    /// Lets go of everything.
+    Disconnected,
+}
+
+/// Somewhere gamepad events come from.
+pub trait Device {
+    /// Adds what happened since the last poll, `dt` ago.
+    fn poll(&mut self, dt: Duration, events: &mut Vec<Event>);
+}
+
+/// Goes from 0 at `dead_zone` to 1 at full travel, along a power curve.
+fn shape(amount: f32, dead_zone: f32, exponent: f32) -> f32 {
+    if amount <= dead_zone {
+        0.0
+    } else {
+        ((amount - dead_zone) / (1.0 - dead_zone))
+            .min(1.0)
+            .powf(exponent)
+    }
+}
+
+/// Every connected gamepad merged into one, feeding sticks and triggers into
+/// `input::Input` and handing back button presses.
+pub struct Gamepads {
+    pub devices: Vec<Box<dyn Device>>,
+    /// How far a stick has to be pushed before it does anything, 0 to 1.
+    /// Round, so diagonals don't snap to an axis.
+    pub dead_zone: f32,
+    pub trigger_dead_zone: f32,
+    /// Sensitivity curve past the dead zone, 1 is linear and higher gives
+    /// finer control near the center.
+    pub exponent: f32,
+    raw: HashMap<Analog, f32>,
+    down: HashSet<GamepadButton>,
+    events: Vec<Event>,
+}
+
+impl Gamepads {
+    /// With the system's gamepads when built with the `gamepad` feature.
+    pub fn new() -> Self {
+        #[allow(unused_mut)]
+        let mut devices: Vec<Box<dyn Device>> = Vec::new();
+        #[cfg(feature = "gamepad")]
+        match Gilrs::new() {
+            Ok(gilrs) => devices.push(Box::new(gilrs)),
+            Err(e) => log::warn!("No gamepads: {:?}", e),
+        }
+
+        Self {
+            devices,
+            dead_zone: 0.15,
+            trigger_dead_zone: 0.05,
+            exponent: 2.0,
+            raw: HashMap::new(),
+            down: HashSet::new(),
+            events: Vec::new(),
+        }
+    }
+
+    /// Polls every device, sets `input`'s analog values and returns the
+    /// buttons pressed (true) and released since the last update.
+    pub fn update(&mut self, input: &mut input::Input, dt: Duration) -> Vec<(GamepadButton, bool)> {
+        let mut events = std::mem::take(&mut self.events);
+        for device in self.devices.iter_mut() {
+            device.poll(dt, &mut events);
+        }
+
+        let mut buttons = Vec::new();
+        for event in events.drain(..) {
+            match event {
+                Event::Button(button, pressed) => {
+                    let changed = if pressed {
+                        self.down.insert(button)
+                    } else {
+                        self.down.remove(&button)
+                    };
+                    if changed {
+                        buttons.push((button, pressed));
+                    }
+                }
+                Event::Analog(analog, value) => {
+                    self.raw.insert(analog, value);
+                }
+                Event::Disconnected => {
+                    self.raw.clear();
+                    buttons.extend(self.down.drain().map(|button| (button, false)));
+                }
+            }
+        }
+        self.events = events;
+
+        let raw = |analog| self.raw.get(&analog).copied().unwrap_or(0.0);
+        for &(x, y) in [
+            (Analog::LeftStickX, Analog::LeftStickY),
+            (Analog::RightStickX, Analog::RightStickY),
+        ]
+        .iter()
+        {
+            let (raw_x, raw_y) = (raw(x), raw(y));
+            let length = (raw_x * raw_x + raw_y * raw_y).sqrt();
+            let scale = if length > 0.0 {
+                shape(length, self.dead_zone, self.exponent) / length
+            } else {
+                0.0
+            };
+            input.set_analog(x, raw_x * scale);
+            input.set_analog(y, raw_y * scale);
+        }
+        for &trigger in [Analog::LeftTrigger, Analog::RightTrigger].iter() {
+            let value = shape(raw(trigger), self.trigger_dead_zone, self.exponent);
+            input.set_analog(trigger, value);
+        }
+
+        buttons
+    }
+}
+
+/// A gamepad that plays back events from a script, for trying out bindings
+/// and dead zones without a real one and for repeatable runs. One event per
+/// line:
+///
+/// ```text
+/// # seconds input value
+/// 0.0 GamepadLeftStickY 1.0
+/// 1.5 GamepadNorth down
+/// 1.6 GamepadNorth up
+/// ```
+///
+/// Sticks and triggers take their raw value, buttons `down` or `up`. A line
+/// of just seconds and `disconnect` unplugs it.
+#[derive(Debug, Default)]
+pub struct VirtualGamepad {
+    /// Sorted by time.
+    pub events: Vec<(f32, Event)>,
+    time: f32,
+    next: usize,
+}
+
+impl VirtualGamepad {
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut events: Vec<(f32, Event)> = Vec::new();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let disconnect = fields.len() == 2 && fields[1] == "disconnect";
+            if fields.len() != 3 && !disconnect {
+                bail!(
+                    "Line {}: expected seconds input value, got {:?}",
+                    number + 1,
+                    line
+                );
+            }
+            let time: f32 = fields[0]
+                .parse()
+                .map_err(|_| anyhow!("Line {}: {:?} is not a number", number + 1, fields[0]))?;
+            if events.last().is_some_and(|&(last, _)| time < last) {
+                bail!("Line {}: times can't go back", number + 1);
+            }
+
+            let event = if disconnect {
+                Event::Disconnected
+            } else if let Some(analog) = Analog::from_name(fields[1]) {
+                let value = fields[2]
+                    .parse()
+                    .map_err(|_| anyhow!("Line {}: {:?} is not a number", number + 1, fields[2]))?;
+                Event::Analog(analog, value)
+            } else if let Some(input::Button::Gamepad(button)) = input::Button::from_name(fields[1])
+            {
+                let pressed = match fields[2] {
+                    "down" => true,
+                    "up" => false,
+                    other => bail!("Line {}: expected down or up, got {:?}", number + 1, other),
+                };
+                Event::Button(button, pressed)
+            } else {
+                bail!("Line {}: unknown gamepad input {:?}", number + 1, fields[1]);
+            };
+            events.push((time, event));
+        }
+
+        Ok(Self {
+            events,
+            ..Self::default()
+        })
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("Reading gamepad script {}", path.display()))?;
+        Self::parse(&text).with_context(|| format!("Parsing gamepad script {}", path.display()))
+    }
+}
+
+impl Device for VirtualGamepad {
+    fn poll(&mut self, dt: Duration, events: &mut Vec<Event>) {
+        self.time += dt.as_secs_f32();
+        while let Some(&(time, event)) = self.events.get(self.next) {
+            if time > self.time {
+                break;
+            }
+            events.push(event);
+            self.next += 1;
+        }
+    }
+}
+
+/// The system's gamepads.
+#[cfg(feature = "gamepad")]
+pub struct Gilrs(gilrs::Gilrs);
+
+#[cfg(feature = "gamepad")]
+impl Gilrs {
+    pub fn new() -> Result<Self> {
+        // Dead zones are ours
+        gilrs::GilrsBuilder::new()
+            .with_default_filters(false)
+            .build()
+            .map(Self)
+            .map_err(|e| anyhow!("{}", e))
+    }
+
+    fn button(button: gilrs::Button) -> Option<GamepadButton> {
+        use gilrs::Button as B;
+        Some(match button {
+            B::South => GamepadButton::South,
+            B::East => GamepadButton::East,
+            B::North => GamepadButton::North,
+            B::West => GamepadButton::West,
+            B::LeftTrigger => GamepadButton::LeftBumper,
+            B::RightTrigger => GamepadButton::RightBumper,
+            B::Select => GamepadButton::Select,
+            B::Start => GamepadButton::Start,
+            B::LeftThumb => GamepadButton::LeftStick,
+            B::RightThumb => GamepadButton::RightStick,
+            B::DPadUp => GamepadButton::DPadUp,
+            B::DPadDown => GamepadButton::DPadDown,
+            B::DPadLeft => GamepadButton::DPadLeft,
+            B::DPadRight => GamepadButton::DPadRight,
+            _ => return None,
+        })
+    }
+}
+
+#[cfg(feature = "gamepad")]
+impl Device for Gilrs {
+    fn poll(&mut self, _dt: Duration, events: &mut Vec<Event>) {
+        use gilrs::{Axis, Button, EventType};
+        while let Some(gilrs::Event { event, .. }) = self.0.next_event() {
+            let event = match event {
+                EventType::ButtonPressed(button, _) => {
+                    Self::button(button).map(|button| Event::Button(button, true))
+                }
+                EventType::ButtonReleased(button, _) => {
+                    Self::button(button).map(|button| Event::Button(button, false))
+                }
+                EventType::ButtonChanged(Button::LeftTrigger2, value, _) => {
+                    Some(Event::Analog(Analog::LeftTrigger, value))
+                }
+                EventType::ButtonChanged(Button::RightTrigger2, value, _) => {
+                    Some(Event::Analog(Analog::RightTrigger, value))
+                }
+                EventType::AxisChanged(axis, value, _) => match axis {
+                    Axis::LeftStickX => Some(Event::Analog(Analog::LeftStickX, value)),
+                    Axis::LeftStickY => Some(Event::Analog(Analog::LeftStickY, value)),
+                    Axis::RightStickX => Some(Event::Analog(Analog::RightStickX, value)),
+                    Axis::RightStickY => Some(Event::Analog(Analog::RightStickY, value)),
+                    _ => None,
+                },
+                EventType::Disconnected => Some(Event::Disconnected),
+                _ => None,
+            };
+            events.extend(event);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::camera;
+    use cgmath::{MetricSpace, Rad};
+    use input::{Axis, Button, Input};
+    use winit::event::VirtualKeyCode;
+
+    const FRAME: Duration = Duration::from_millis(10);
+
+    /// Gamepads playing back `script` and nothing else.
+    fn scripted(script: &str) -> Gamepads {
+        let mut gamepads = Gamepads::new();
+        gamepads.devices = vec![Box::new(VirtualGamepad::parse(script).unwrap())];
+        gamepads
+    }
+
+    #[test]
+    fn dead_zone_reads_zero() {
+        let mut input = Input::new(input::Bindings::defaults());
+        let mut gamepads = scripted("0.0 GamepadLeftStickY 0.1\n0.5 GamepadLeftStickY 0.2");
+        gamepads.update(&mut input, FRAME);
+        assert_eq!(input.axis(Axis::MoveForward), 0.0);
+
+        gamepads.update(&mut input, Duration::from_secs(1));
+        assert!(input.axis(Axis::MoveForward) > 0.0);
+    }
+
+    #[test]
+    fn curve_is_monotonic_up_to_full_deflection() {
+        let mut last = 0.0;
+        for i in 0..=100 {
+            let value = shape(i as f32 / 100.0, 0.15, 2.0);
+            assert!(value >= last, "{} then {}", last, value);
+            last = value;
+        }
+        assert_eq!(shape(1.0, 0.15, 2.0), 1.0);
+
+        let mut input = Input::new(input::Bindings::defaults());
+        scripted("0.0 GamepadLeftStickY -1.0").update(&mut input, FRAME);
+        assert_eq!(input.axis(Axis::MoveForward), -1.0);
+        scripted("0.0 GamepadLeftStickY 1.0").update(&mut input, FRAME);
+        assert_eq!(input.axis(Axis::MoveForward), 1.0);
+    }
+
+    #[test]
+    fn stick_moves_like_keys() {
+        fn fly(input: &Input) -> camera::Camera {
+            let mut controller = camera::Controller::new(10.0, 0.004);
+            let mut camera = camera::Camera::new((0.0, 0.0, 0.0), Rad(0.0), Rad(0.0));
+            for _ in 0..60 {
+                controller.process_input(input);
+                controller.update_camera(&mut camera, FRAME, |_, _| 0.0);
+            }
+            camera
+        }
+
+        let mut keys = Input::new(input::Bindings::defaults());
+        keys.process(Button::Key(VirtualKeyCode::W), true);
+        let mut stick = Input::new(input::Bindings::defaults());
+        scripted("0.0 GamepadLeftStickY 1.0").update(&mut stick, FRAME);
+
+        let (a, b) = (fly(&keys).pos, fly(&stick).pos);
+        assert!(a.distance(b) < 1e-4, "{:?} {:?}", a, b);
+        assert!(a.x > 1.0);
+    }
+
+    #[test]
+    fn buttons_come_back_and_disconnect_releases_them() {
+        let mut input = Input::new(input::Bindings::defaults());
+        let mut gamepads = scripted("0.0 GamepadNorth down\n0.0 GamepadNorth down\n1.0 disconnect");
+        assert_eq!(
+            gamepads.update(&mut input, FRAME),
+            vec![(GamepadButton::North, true)]
+        );
+        assert_eq!(
+            gamepads.update(&mut input, Duration::from_secs(1)),
+            vec![(GamepadButton::North, false)]
+        );
+    }
+}
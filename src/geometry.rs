@@ -0,0 +1,288 @@
+use std::ops::Range;
+
+use crate::mesh;
+
+/// Vertices and indices the pool starts with room for, it grows as needed.
+const INITIAL_VERTICES: u64 = 1 << 16;
+const INITIAL_INDICES: u64 = 1 << 18;
+
+/// Same layout as wgpu's DrawIndexedIndirect.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct DrawCommand {
+    pub index_count: u32,
+    pub instance_count: u32,
+    pub first_index: u32,
+    pub base_vertex: i32,
+    pub first_instance: u32,
+}
+
+pub const DRAW_COMMAND_SIZE: u64 = std::mem::size_of::<DrawCommand>() as u64;
+
+/// One vertex and one index buffer shared by every mesh, so drawing them
+/// never needs a buffer switch. Indices are stored already offset by where
+/// the mesh's vertices ended up, so draws always use a base vertex of 0.
+pub struct GeometryPool {
+    pub vertices: wgpu::Buffer,
+    pub indices: wgpu::Buffer,
+    /// Vertices (binding 0) and indices (binding 1) as read only storage
+    /// buffers, for shaders that pull vertices themselves.
+    pub storage: wgpu::BindGroup,
+    pub storage_layout: wgpu::BindGroupLayout,
+    /// Whether the device can do `multi_draw_indexed_indirect`.
+    pub multi_draw: bool,
+    vertex_capacity: u64,
+    index_capacity: u64,
+    vertex_count: u64,
+    index_count: u64,
+}
+
+fn create_buffer(
+    device: &wgpu::Device,
+    label: &str,
+    usage: wgpu::BufferUsages,
+    size: u64,
+) -> wgpu::Buffer {
+    device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some(label),
+        size,
+        usage: usage
+            | wgpu::BufferUsages::STORAGE
+            | wgpu::BufferUsages::COPY_DST
+            | wgpu::BufferUsages::COPY_SRC,
+        mapped_at_creation: false,
+    })
+}
+
+impl GeometryPool {
+    pub fn new(device: &wgpu::Device) -> Self {
+        let storage_entry = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only: true },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+
+        let storage_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[storage_entry(0), storage_entry(1)],
+            label: Some("Geometry Storage Bind Group Layout"),
+        });
+
+        let vertices = Self::vertex_buffer(device, INITIAL_VERTICES);
+        let indices = Self::index_buffer(device, INITIAL_INDICES);
+        let storage = Self::storage_group(device, &storage_layout, &vertices, &indices);
+
+        Self {
+            vertices,
+            indices,
+            storage,
+            storage_layout,
+            multi_draw: device
+                .features()
+                .contains(wgpu::Features::MULTI_DRAW_INDIRECT),
+            vertex_capacity: INITIAL_VERTICES,
+            index_capacity: INITIAL_INDICES,
+            vertex_count: 0,
+            index_count: 0,
+        }
+    }
+
+    fn vertex_buffer(device: &wgpu::Device, capacity: u64) -> wgpu::Buffer {
+        let size = capacity * std::mem::size_of::<mesh::Vertex>() as u64;
+        create_buffer(device, "Vertex Buffer", wgpu::BufferUsages::VERTEX, size)
+    }
+
+    fn index_buffer(device: &wgpu::Device, capacity: u64) -> wgpu::Buffer {
+        let size = capacity * std::mem::size_of::<u32>() as u64;
+        create_buffer(device, "Index Buffer", wgpu::BufferUsages::INDEX, size)
+    }
+
+    fn storage_group(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        vertices: &wgpu::Buffer,
+        indices: &wgpu::Buffer,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: vertices.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: indices.as_entire_binding(),
+                },
+            ],
+            label: Some("Geometry Storage Bind Group"),
+        })
+    }
+
+    /// Makes room for `vertices` and `indices` more, copying what's there
+    /// into bigger buffers if needed.
+    fn reserve(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, vertices: u64, indices: u64) {
+        let vertex_capacity = (self.vertex_count + vertices)
+            .next_power_of_two()
+            .max(self.vertex_capacity);
+        let index_capacity = (self.index_count + indices)
+            .next_power_of_two()
+            .max(self.index_capacity);
+        if vertex_capacity == self.vertex_capacity && index_capacity == self.index_capacity {
+            return;
+        }
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Geometry Pool Grow Encoder"),
+        });
+
+        if vertex_capacity != self.vertex_capacity {
+            let buffer = Self::vertex_buffer(device, vertex_capacity);
+            let used = self.vertex_count * std::mem::size_of::<mesh::Vertex>() as u64;
+            encoder.copy_buffer_to_buffer(&self.vertices, 0, &buffer, 0, used);
+            self.vertices = buffer;
+            self.vertex_capacity = vertex_capacity;
+        }
+
+        if index_capacity != self.index_capacity {
+            let buffer = Self::index_buffer(device, index_capacity);
+            let used = self.index_count * std::mem::size_of::<u32>() as u64;
+            encoder.copy_buffer_to_buffer(&self.indices, 0, &buffer, 0, used);
+            self.indices = buffer;
+            self.index_capacity = index_capacity;
+        }
+
+        queue.submit(std::iter::once(encoder.finish()));
+
+        self.storage =
+            Self::storage_group(device, &self.storage_layout, &self.vertices, &self.indices);
+    }
+
+    /// Copies the geometry into the pool, returning where its indices went.
+    pub fn add(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        vertices: &[mesh::Vertex],
+        indices: &[u32],
+    ) -> Range<u32> {
+        self.reserve(device, queue, vertices.len() as u64, indices.len() as u64);
+
+        let base_vertex = self.vertex_count as u32;
+        let offset_indices: Vec<u32> = indices.iter().map(|index| index + base_vertex).collect();
+
+        queue.write_buffer(
+            &self.vertices,
+            self.vertex_count * std::mem::size_of::<mesh::Vertex>() as u64,
+            bytemuck::cast_slice(vertices),
+        );
+        queue.write_buffer(
+            &self.indices,
+            self.index_count * std::mem::size_of::<u32>() as u64,
+            bytemuck::cast_slice(&offset_indices),
+        );
+
+        let first_index = self.index_count as u32;
+        self.vertex_count += vertices.len() as u64;
+        self.index_count += indices.len() as u64;
+        first_index..self.index_count as u32
+    }
+
+    /// Binds the vertex and index buffers.
+    pub fn bind<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) {
+        render_pass.set_vertex_buffer(0, self.vertices.slice(..));
+        render_pass.set_index_buffer(self.indices.slice(..), wgpu::IndexFormat::Uint32);
+    }
+
+    /// Runs `commands` out of `buffer`, a single call if the device can
+    /// multi draw. Without `indexed` each command is read as DrawIndirect,
+    /// whose first three fields line up with DrawIndexedIndirect: index count
+    /// and first index become vertex count and first vertex. That only holds
+    /// one command at a time, multi draw would step through the buffer by
+    /// the smaller DrawIndirect size.
+    pub fn draw_indirect<'a>(
+        &self,
+        render_pass: &mut wgpu::RenderPass<'a>,
+        buffer: &'a wgpu::Buffer,
+        commands: Range<u32>,
+        indexed: bool,
+    ) {
+        let offset = commands.start as u64 * DRAW_COMMAND_SIZE;
+        let count = commands.end - commands.start;
+
+        if self.multi_draw && indexed {
+            render_pass.multi_draw_indexed_indirect(buffer, offset, count);
+        } else {
+            for i in 0..count as u64 {
+                let offset = offset + i * DRAW_COMMAND_SIZE;
+                if indexed {
+                    render_pass.draw_indexed_indirect(buffer, offset);
+                } else {
+                    render_pass.draw_indirect(buffer, offset);
+                }
+            }
+        }
+    }
+}
+
+/// Draw commands written on the CPU each frame.
+pub struct DrawList {
+    pub buffer: wgpu::Buffer,
+    commands: Vec<DrawCommand>,
+    // In commands
+    capacity: usize,
+    len: u32,
+}
+
+impl DrawList {
+    pub fn new(device: &wgpu::Device) -> Self {
+        let capacity = 64;
+        Self {
+            buffer: Self::create_buffer(device, capacity),
+            commands: Vec::new(),
+            capacity,
+            len: 0,
+        }
+    }
+
+    fn create_buffer(device: &wgpu::Device, capacity: usize) -> wgpu::Buffer {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Draw List Buffer"),
+            size: capacity as u64 * DRAW_COMMAND_SIZE,
+            usage: wgpu::BufferUsages::INDIRECT | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        })
+    }
+
+    /// Draws `indices` of the pool once.
+    pub fn push(&mut self, indices: Range<u32>) {
+        self.commands.push(DrawCommand {
+            index_count: indices.end - indices.start,
+            instance_count: 1,
+            first_index: indices.start,
+            base_vertex: 0,
+            first_instance: 0,
+        });
+    }
+
+    /// Sends this frame's commands to the GPU and starts over.
+    pub fn upload(&mut self, device: &wgpu::Device, queue: &wgpu::Queue) {
+        if self.commands.len() > self.capacity {
+            self.capacity = self.commands.len().next_power_of_two();
+            self.buffer = Self::create_buffer(device, self.capacity);
+        }
+
+        queue.write_buffer(&self.buffer, 0, bytemuck::cast_slice(&self.commands));
+        self.len = self.commands.len() as u32;
+        self.commands.clear();
+    }
+
+    /// Everything uploaded last.
+    pub fn commands(&self) -> Range<u32> {
+        0..self.len
+    }
+}
@@ -0,0 +1,490 @@
+use anyhow::*;
+use cgmath::InnerSpace;
+use wgpu::util::DeviceExt;
+
+use crate::culling;
+use crate::geometry;
+use crate::mesh;
+use crate::preprocessor::{Defines, Preprocessor};
+use crate::texture;
+
+/// Candidate points scattered over every terrain triangle.
+const SAMPLES_PER_TRIANGLE: u32 = 3;
+/// Vertices of a grass blade and of a foliage card, see grass.wgsl.
+const BLADE_VERTICES: u32 = 9;
+const CARD_VERTICES: u32 = 12;
+/// Size of wgpu's DrawIndirect.
+const DRAW_SIZE: u64 = 16;
+
+/// A grass blade or foliage card as written by the scatter shader.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct Instance {
+    pub position: [f32; 4],
+    pub params: [f32; 4],
+}
+
+impl Instance {
+    pub fn layout<'a>() -> wgpu::VertexBufferLayout<'a> {
+        const ATTRIBUTES: [wgpu::VertexAttribute; 2] = wgpu::vertex_attr_array![
+            0 => Float32x4, // Position, rotation
+            1 => Float32x4  // Height, width, random, kind
+        ];
+
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<Instance>() as u64,
+            step_mode: wgpu::VertexStepMode::Instance,
+            attributes: &ATTRIBUTES,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct Chunk {
+    first_index: u32,
+    index_count: u32,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct ScatterParams {
+    rules: [f32; 4],
+    foliage_chance: f32,
+    samples_per_triangle: u32,
+    capacity: u32,
+    _padding: u32,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct GrassUniform {
+    wind: [f32; 4],
+    fade: [f32; 4],
+}
+
+/// Where things grow.
+pub struct Rules {
+    /// Nothing grows below this, e.g. under water.
+    pub min_height: f32,
+    pub max_height: f32,
+    /// Steepest slope anything grows on.
+    pub max_slope: cgmath::Deg<f32>,
+    /// Chance of a candidate point growing something where the density map
+    /// is white.
+    pub density: f32,
+    /// Fraction of what grows that is foliage instead of grass.
+    pub foliage: f32,
+}
+
+/// Grass blades and foliage cards over a terrain mesh. A compute shader
+/// scatters them over the terrain's triangles following `rules` and a
+/// density map, counting them into one DrawIndirect per chunk and kind, so
+/// they are culled along with the terrain chunk they grow on.
+pub struct Grass {
+    pub enabled: bool,
+    pub rules: Rules,
+    /// Scattering runs again before the next frame when set, e.g. after
+    /// changing `rules`.
+    pub dirty: bool,
+    /// Direction along x and z, scaled by strength.
+    pub wind: cgmath::Vector2<f32>,
+    /// Instances start thinning out at the first distance and are all gone
+    /// at the second.
+    pub fade: (f32, f32),
+    pub layout: wgpu::BindGroupLayout,
+    pub bind_group: wgpu::BindGroup,
+    chunk_count: u32,
+    capacity: u32,
+    candidates: u32,
+    instances: wgpu::Buffer,
+    draws: wgpu::Buffer,
+    params: wgpu::Buffer,
+    uniform: wgpu::Buffer,
+    scatter_layout: wgpu::BindGroupLayout,
+    scatter_group: wgpu::BindGroup,
+    scatter: wgpu::ComputePipeline,
+}
+
+fn compute_entry(binding: u32, ty: wgpu::BindingType) -> wgpu::BindGroupLayoutEntry {
+    wgpu::BindGroupLayoutEntry {
+        binding,
+        visibility: wgpu::ShaderStages::COMPUTE,
+        ty,
+        count: None,
+    }
+}
+
+fn storage(read_only: bool) -> wgpu::BindingType {
+    wgpu::BindingType::Buffer {
+        ty: wgpu::BufferBindingType::Storage { read_only },
+        has_dynamic_offset: false,
+        min_binding_size: None,
+    }
+}
+
+impl Grass {
+    /// Grows on the submeshes of `terrain`, which has to be in `pool`.
+    pub fn new(
+        device: &wgpu::Device,
+        shaders: &mut Preprocessor,
+        pool: &geometry::GeometryPool,
+        terrain: &mesh::Mesh,
+        density_map: &texture::Texture,
+        foliage: &texture::Texture,
+        rules: Rules,
+    ) -> Result<Self> {
+        let chunks: Vec<Chunk> = terrain
+            .submeshes
+            .iter()
+            .map(|submesh| Chunk {
+                first_index: submesh.indices.start,
+                index_count: submesh.indices.end - submesh.indices.start,
+            })
+            .collect();
+        let chunk_count = chunks.len() as u32;
+        let candidates = chunks
+            .iter()
+            .map(|chunk| chunk.index_count / 3 * SAMPLES_PER_TRIANGLE)
+            .max()
+            .unwrap_or(0);
+        // Can't run out of room, every candidate fits
+        let capacity = candidates;
+
+        let chunk_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Grass Chunk Buffer"),
+            contents: bytemuck::cast_slice(&chunks),
+            usage: wgpu::BufferUsages::STORAGE,
+        });
+        let instances = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Grass Instance Buffer"),
+            size: (chunk_count * 2 * capacity).max(1) as u64
+                * std::mem::size_of::<Instance>() as u64,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::VERTEX,
+            mapped_at_creation: false,
+        });
+        let draws = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Grass Draw Buffer"),
+            size: (chunk_count * 2).max(1) as u64 * DRAW_SIZE,
+            usage: wgpu::BufferUsages::STORAGE
+                | wgpu::BufferUsages::INDIRECT
+                | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let params = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Grass Scatter Params Buffer"),
+            size: std::mem::size_of::<ScatterParams>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let uniform = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Grass Uniform Buffer"),
+            size: std::mem::size_of::<GrassUniform>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let uniform_type = wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Uniform,
+            has_dynamic_offset: false,
+            min_binding_size: None,
+        };
+        let texture_type = wgpu::BindingType::Texture {
+            multisampled: false,
+            sample_type: wgpu::TextureSampleType::Float { filterable: true },
+            view_dimension: wgpu::TextureViewDimension::D2,
+        };
+        let sampler_type = wgpu::BindingType::Sampler {
+            comparison: false,
+            filtering: true,
+        };
+
+        let scatter_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Grass Scatter Bind Group Layout"),
+            entries: &[
+                compute_entry(0, storage(true)),
+                compute_entry(1, storage(false)),
+                compute_entry(2, storage(false)),
+                compute_entry(3, uniform_type),
+                compute_entry(4, texture_type),
+                compute_entry(5, sampler_type),
+            ],
+        });
+        let scatter_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Grass Scatter Bind Group"),
+            layout: &scatter_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: chunk_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: instances.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: draws.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: params.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: wgpu::BindingResource::TextureView(&density_map.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 5,
+                    resource: wgpu::BindingResource::Sampler(&density_map.sampler),
+                },
+            ],
+        });
+
+        let fragment_entry = |binding, ty| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
+            ty,
+            count: None,
+        };
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Grass Bind Group Layout"),
+            entries: &[
+                fragment_entry(0, uniform_type),
+                fragment_entry(1, texture_type),
+                fragment_entry(2, sampler_type),
+            ],
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Grass Bind Group"),
+            layout: &layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: uniform.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&foliage.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::Sampler(&foliage.sampler),
+                },
+            ],
+        });
+
+        let scatter = Self::create_pipeline(device, shaders, pool, &scatter_layout)?;
+
+        Ok(Self {
+            enabled: true,
+            rules,
+            dirty: true,
+            wind: cgmath::Vector2::new(0.8, 0.3),
+            fade: (60.0, 150.0),
+            layout,
+            bind_group,
+            chunk_count,
+            capacity,
+            candidates,
+            instances,
+            draws,
+            params,
+            uniform,
+            scatter_layout,
+            scatter_group,
+            scatter,
+        })
+    }
+
+    fn create_pipeline(
+        device: &wgpu::Device,
+        shaders: &mut Preprocessor,
+        pool: &geometry::GeometryPool,
+        scatter_layout: &wgpu::BindGroupLayout,
+    ) -> Result<wgpu::ComputePipeline> {
+        let module = shaders.module(
+            device,
+            "grass.wgsl",
+            &Defines::new(&["SCATTER"]),
+            &["scatter"],
+        )?;
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Grass Scatter Pipeline Layout"),
+            bind_group_layouts: &[&pool.storage_layout, scatter_layout],
+            push_constant_ranges: &[],
+        });
+        Ok(
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some("Grass Scatter Pipeline"),
+                layout: Some(&layout),
+                module: &module,
+                entry_point: "scatter",
+            }),
+        )
+    }
+
+    /// Rebuilds the scatter pipeline and scatters again, keeping the old
+    /// pipeline on errors.
+    pub fn reload(
+        &mut self,
+        device: &wgpu::Device,
+        shaders: &mut Preprocessor,
+        pool: &geometry::GeometryPool,
+    ) {
+        match Self::create_pipeline(device, shaders, pool, &self.scatter_layout) {
+            Ok(pipeline) => {
+                self.scatter = pipeline;
+                self.dirty = true;
+            }
+            Err(e) => log::error!("{:?}", e),
+        }
+    }
+
+    /// Scatters everything again if `dirty`, on its own submission.
+    pub fn scatter(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        pool: &geometry::GeometryPool,
+    ) {
+        if !self.dirty {
+            return;
+        }
+        self.dirty = false;
+
+        let rules = &self.rules;
+        let min_normal_y = cgmath::Rad::from(rules.max_slope).0.cos();
+        queue.write_buffer(
+            &self.params,
+            0,
+            bytemuck::cast_slice(&[ScatterParams {
+                rules: [
+                    rules.min_height,
+                    rules.max_height,
+                    min_normal_y,
+                    rules.density,
+                ],
+                foliage_chance: rules.foliage,
+                samples_per_triangle: SAMPLES_PER_TRIANGLE,
+                capacity: self.capacity,
+                _padding: 0,
+            }]),
+        );
+
+        // Counted up again by the shader
+        let draws: Vec<[u32; 4]> = (0..self.chunk_count)
+            .flat_map(|_| [[BLADE_VERTICES, 0, 0, 0], [CARD_VERTICES, 0, 0, 0]])
+            .collect();
+        queue.write_buffer(&self.draws, 0, bytemuck::cast_slice(&draws));
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Grass Scatter Encoder"),
+        });
+        {
+            let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("Grass Scatter"),
+            });
+            pass.set_pipeline(&self.scatter);
+            pass.set_bind_group(0, &pool.storage, &[]);
+            pass.set_bind_group(1, &self.scatter_group, &[]);
+            pass.dispatch(self.candidates.div_ceil(64), self.chunk_count, 1);
+        }
+        queue.submit(std::iter::once(encoder.finish()));
+    }
+
+    pub fn update(&self, queue: &wgpu::Queue, time: f32) {
+        let strength = self.wind.magnitude();
+        let direction = if strength > 0.0 {
+            self.wind / strength
+        } else {
+            self.wind
+        };
+
+        queue.write_buffer(
+            &self.uniform,
+            0,
+            bytemuck::cast_slice(&[GrassUniform {
+                wind: [direction.x, direction.y, strength, time],
+                fade: [self.fade.0, self.fade.1, 0.0, 0.0],
+            }]),
+        );
+    }
+
+    /// Draws what grows on each of `chunks` close enough to `camera_pos` to
+    /// not have faded out. Indices into the terrain's submeshes, e.g. the ones
+    /// left after frustum culling. Pipeline and camera (group 1) are up to
+    /// the caller.
+    pub fn draw<'a>(
+        &'a self,
+        render_pass: &mut wgpu::RenderPass<'a>,
+        terrain: &mesh::Mesh,
+        chunks: &[usize],
+        camera_pos: cgmath::Point3<f32>,
+    ) {
+        if !self.enabled {
+            return;
+        }
+
+        render_pass.set_bind_group(0, &self.bind_group, &[]);
+        let region = self.capacity as u64 * std::mem::size_of::<Instance>() as u64;
+        for &chunk in chunks {
+            if distance_xz(&terrain.submeshes[chunk].bounds.aabb, camera_pos) > self.fade.1 {
+                continue;
+            }
+
+            for kind in 0..2 {
+                let draw = chunk as u64 * 2 + kind;
+                render_pass
+                    .set_vertex_buffer(0, self.instances.slice(draw * region..(draw + 1) * region));
+                render_pass.draw_indirect(&self.draws, draw * DRAW_SIZE);
+            }
+        }
+    }
+}
+
+/// Horizontal distance from `point` to the box, 0 above or below it.
+fn distance_xz(aabb: &culling::Aabb, point: cgmath::Point3<f32>) -> f32 {
+    let dx = (aabb.min.x - point.x).max(point.x - aabb.max.x).max(0.0);
+    let dz = (aabb.min.z - point.z).max(point.z - aabb.max.z).max(0.0);
+    (dx * dx + dz * dz).sqrt()
+}
+
+/// Where grass grows thickest, blotches of value noise. Any image works,
+/// only red is used.
+pub fn density_map(device: &wgpu::Device, queue: &wgpu::Queue) -> texture::Texture {
+    const SIZE: u32 = 256;
+    const CELLS: u32 = 8;
+
+    let lattice = |x: u32, y: u32| {
+        let mut h = (x % CELLS).wrapping_mul(374_761_393) ^ (y % CELLS).wrapping_mul(668_265_263);
+        h = (h ^ (h >> 13)).wrapping_mul(1_274_126_177);
+        (h ^ (h >> 16)) as f32 / u32::MAX as f32
+    };
+
+    let image = image::RgbaImage::from_fn(SIZE, SIZE, |x, y| {
+        let (u, v) = (
+            x as f32 * CELLS as f32 / SIZE as f32,
+            y as f32 * CELLS as f32 / SIZE as f32,
+        );
+        let (cx, cy) = (u as u32, v as u32);
+        let (fx, fy) = (u.fract(), v.fract());
+        let (sx, sy) = (fx * fx * (3.0 - 2.0 * fx), fy * fy * (3.0 - 2.0 * fy));
+
+        let top = lattice(cx, cy) + (lattice(cx + 1, cy) - lattice(cx, cy)) * sx;
+        let bottom = lattice(cx, cy + 1) + (lattice(cx + 1, cy + 1) - lattice(cx, cy + 1)) * sx;
+        let noise = top + (bottom - top) * sy;
+
+        let value = (noise * 1.6 - 0.3).clamp(0.0, 1.0);
+        let byte = (value * 255.0) as u8;
+        image::Rgba([byte, byte, byte, 255])
+    });
+
+    texture::Texture::from_image(
+        device,
+        queue,
+        &image::DynamicImage::ImageRgba8(image),
+        Some("Grass Density Map"),
+    )
+    .unwrap()
+}
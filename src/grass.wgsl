@@ -0,0 +1,295 @@
+#include "camera.wgsl"
+
+// Grass blades and foliage cards scattered over the terrain. SCATTER is the
+// compute shader placing them, without it they get drawn.
+
+struct Instance {
+    // Position, rotation around y
+    position: vec4<f32>;
+    // Height, width, random 0..1, kind (0 grass, 1 foliage)
+    params: vec4<f32>;
+};
+
+#ifdef SCATTER
+[[block]]
+struct Floats {
+    data: [[stride(4)]] array<f32>;
+};
+
+[[block]]
+struct Indices {
+    data: [[stride(4)]] array<u32>;
+};
+
+// A terrain chunk, part of the pool's index buffer
+struct Chunk {
+    first_index: u32;
+    index_count: u32;
+};
+
+[[block]]
+struct Chunks {
+    data: [[stride(8)]] array<Chunk>;
+};
+
+[[block]]
+struct Instances {
+    data: [[stride(32)]] array<Instance>;
+};
+
+// wgpu's DrawIndirect, instances get counted up while scattering
+struct DrawArgs {
+    vertex_count: u32;
+    instance_count: atomic<u32>;
+    first_vertex: u32;
+    first_instance: u32;
+};
+
+[[block]]
+struct Draws {
+    data: [[stride(16)]] array<DrawArgs>;
+};
+
+[[block]]
+struct ScatterParams {
+    // Min height, max height, min normal y, density
+    rules: vec4<f32>;
+    foliage_chance: f32;
+    samples_per_triangle: u32;
+    // Instances per chunk and kind, as many as there are candidate points
+    capacity: u32;
+};
+
+[[group(0), binding(0)]]
+var<storage, read> vertices: Floats;
+
+[[group(0), binding(1)]]
+var<storage, read> indices: Indices;
+
+[[group(1), binding(0)]]
+var<storage, read> chunks: Chunks;
+
+[[group(1), binding(1)]]
+var<storage, read_write> instances: Instances;
+
+[[group(1), binding(2)]]
+var<storage, read_write> draws: Draws;
+
+[[group(1), binding(3)]]
+var<uniform> params: ScatterParams;
+
+[[group(1), binding(4)]]
+var t_density: texture_2d<f32>;
+
+[[group(1), binding(5)]]
+var s_density: sampler;
+
+fn hash(value: u32) -> u32 {
+    var x = value;
+    x = x ^ (x >> 16u);
+    x = x * 2146121005u;
+    x = x ^ (x >> 15u);
+    x = x * 2221713035u;
+    x = x ^ (x >> 16u);
+    return x;
+}
+
+fn unit(value: u32) -> f32 {
+    return f32(value & 16777215u) / 16777216.0;
+}
+
+// mesh::Vertex is 8 floats: position, normal, uv
+fn vertex_position(index: u32) -> vec3<f32> {
+    let base = index * 8u;
+    return vec3<f32>(vertices.data[base], vertices.data[base + 1u], vertices.data[base + 2u]);
+}
+
+fn vertex_uv(index: u32) -> vec2<f32> {
+    let base = index * 8u + 6u;
+    return vec2<f32>(vertices.data[base], vertices.data[base + 1u]);
+}
+
+// x is a candidate point, y the chunk
+[[stage(compute), workgroup_size(64)]]
+fn scatter([[builtin(global_invocation_id)]] id: vec3<u32>) {
+    let chunk_index = id.y;
+    let chunk = chunks.data[chunk_index];
+    let triangle = id.x / params.samples_per_triangle;
+    if (triangle * 3u >= chunk.index_count) {
+        return;
+    }
+
+    let first = chunk.first_index + triangle * 3u;
+    let i0 = indices.data[first];
+    let i1 = indices.data[first + 1u];
+    let i2 = indices.data[first + 2u];
+    let p0 = vertex_position(i0);
+    let p1 = vertex_position(i1);
+    let p2 = vertex_position(i2);
+
+    let h0 = hash(id.x ^ hash(chunk_index + 2654435769u));
+    let h1 = hash(h0);
+    let h2 = hash(h1);
+    let h3 = hash(h2);
+    let h4 = hash(h3);
+
+    // Uniform point in the triangle, folding the far half of the square back
+    var a = unit(h0);
+    var b = unit(h1);
+    if (a + b > 1.0) {
+        a = 1.0 - a;
+        b = 1.0 - b;
+    }
+    let position = p0 + (p1 - p0) * a + (p2 - p0) * b;
+    let uv = vertex_uv(i0) + (vertex_uv(i1) - vertex_uv(i0)) * a + (vertex_uv(i2) - vertex_uv(i0)) * b;
+
+    // The terrain's stored normals all point up, use the face's
+    let normal = normalize(cross(p1 - p0, p2 - p0));
+    let flatness = abs(normal.y);
+
+    let density = params.rules.w * textureSampleLevel(t_density, s_density, uv, 0.0).r;
+    let grows = position.y >= params.rules.x && position.y <= params.rules.y
+        && flatness >= params.rules.z && unit(h2) < density;
+    if (!grows) {
+        return;
+    }
+
+    let foliage = unit(h3) < params.foliage_chance;
+    // Every chunk has a draw and a region of instances for grass followed by
+    // one for foliage
+    let draw = chunk_index * 2u + select(0u, 1u, foliage);
+    let slot = atomicAdd(&draws.data[draw].instance_count, 1u);
+
+    let random = unit(h4);
+    var instance: Instance;
+    instance.position = vec4<f32>(position, unit(hash(h4)) * 6.2831853);
+    instance.params = select(
+        vec4<f32>(0.8 + random * 0.8, 0.06 + random * 0.04, random, 0.0),
+        vec4<f32>(1.2 + random * 1.0, 1.0 + random * 0.6, random, 1.0),
+        vec4<bool>(foliage, foliage, foliage, foliage),
+    );
+    instances.data[draw * params.capacity + slot] = instance;
+}
+#else
+[[block]]
+struct GrassUniform {
+    // Wind direction xz, strength, time in seconds
+    wind: vec4<f32>;
+    // Distance instances start thinning out and where all are gone
+    fade: vec4<f32>;
+};
+
+[[group(0), binding(0)]]
+var<uniform> grass: GrassUniform;
+
+[[group(0), binding(1)]]
+var t_foliage: texture_2d<f32>;
+
+[[group(0), binding(2)]]
+var s_foliage: sampler;
+
+[[group(1), binding(0)]]
+var<uniform> camera: CameraUniform;
+
+[[group(1), binding(1)]]
+var<uniform> atmosphere: AtmosphereUniform;
+
+#include "atmosphere.wgsl"
+
+struct InstanceInput {
+    [[location(0)]] position: vec4<f32>;
+    [[location(1)]] params: vec4<f32>;
+};
+
+struct VertexOutput {
+    [[builtin(position)]] clip_position: vec4<f32>;
+    [[location(0)]] world_position: vec3<f32>;
+    [[location(1)]] uv: vec2<f32>;
+    [[location(2)]] normal: vec3<f32>;
+    [[location(3)]] kind: f32;
+};
+
+// Three segments narrowing to a tip, 9 vertices
+fn blade_corner(index: u32) -> vec2<f32> {
+    var corners = array<vec2<f32>, 9>(
+        vec2<f32>(-1.0, 0.0),
+        vec2<f32>(1.0, 0.0),
+        vec2<f32>(-0.7, 0.45),
+        vec2<f32>(1.0, 0.0),
+        vec2<f32>(0.7, 0.45),
+        vec2<f32>(-0.7, 0.45),
+        vec2<f32>(-0.7, 0.45),
+        vec2<f32>(0.7, 0.45),
+        vec2<f32>(0.0, 1.0),
+    );
+    return corners[index];
+}
+
+// Two crossed quads, 12 vertices, x in -1..1 and y in 0..1
+fn card_corner(index: u32) -> vec2<f32> {
+    var corners = array<vec2<f32>, 6>(
+        vec2<f32>(-1.0, 0.0),
+        vec2<f32>(1.0, 0.0),
+        vec2<f32>(1.0, 1.0),
+        vec2<f32>(-1.0, 0.0),
+        vec2<f32>(1.0, 1.0),
+        vec2<f32>(-1.0, 1.0),
+    );
+    return corners[index % 6u];
+}
+
+[[stage(vertex)]]
+fn main([[builtin(vertex_index)]] vertex_index: u32, instance: InstanceInput) -> VertexOutput {
+    let foliage = instance.params.w > 0.5;
+    let random = instance.params.z;
+    let corner = select(blade_corner(min(vertex_index, 8u)), card_corner(vertex_index), foliage);
+
+    // The second card is turned by 90 degrees
+    let angle = instance.position.w + select(0.0, 1.5707963, foliage && vertex_index >= 6u);
+    let side = vec3<f32>(cos(angle), 0.0, sin(angle));
+
+    // Thins out with distance, each instance disappearing at its own point
+    let distance = distance(camera.view_pos.xz, instance.position.xz);
+    let fade_start = grass.fade.x;
+    let vanish = mix(fade_start, grass.fade.y, random);
+    let scale = clamp((vanish - distance) / max(vanish - fade_start, 0.001) * 4.0, 0.0, 1.0);
+
+    let height = instance.params.x * scale;
+    var local = side * corner.x * instance.params.y * scale + vec3<f32>(0.0, corner.y * height, 0.0);
+
+    // Bend with the wind, more towards the top
+    let wind_direction = vec3<f32>(grass.wind.x, 0.0, grass.wind.y);
+    let phase = dot(instance.position.xz, grass.wind.xy) * 0.15 + random * 2.0;
+    let gust = sin(grass.wind.w * 1.7 + phase) * 0.6 + sin(grass.wind.w * 4.3 + phase * 2.3) * 0.2 + 0.4;
+    local = local + wind_direction * gust * grass.wind.z * corner.y * corner.y * height;
+
+    var out: VertexOutput;
+    out.world_position = instance.position.xyz + local;
+    out.clip_position = camera.view_proj * vec4<f32>(out.world_position, 1.0);
+    out.uv = vec2<f32>(corner.x * 0.5 + 0.5, 1.0 - corner.y);
+    // Leaning up so blades light like the ground they cover
+    out.normal = normalize(cross(side, vec3<f32>(0.0, 1.0, 0.0)) * 0.3 + vec3<f32>(0.0, 1.0, 0.0));
+    out.kind = select(random * 0.5, 1.0, foliage);
+    return out;
+}
+
+[[stage(fragment)]]
+fn main(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    let card = textureSample(t_foliage, s_foliage, in.uv);
+    var color = card.rgb;
+    if (in.kind < 0.75) {
+        // Darker at the root, in.kind carries a bit of per blade variation
+        let root = vec3<f32>(0.05, 0.18, 0.03);
+        let tip = mix(vec3<f32>(0.35, 0.6, 0.15), vec3<f32>(0.55, 0.6, 0.2), in.kind * 2.0);
+        color = mix(tip, root, in.uv.y);
+    } else {
+        if (card.a < 0.5) {
+            discard;
+        }
+    }
+
+    let diffuse = abs(dot(normalize(in.normal), atmosphere.sun.xyz));
+    color = color * (0.3 + 0.7 * diffuse);
+    return vec4<f32>(apply_atmosphere(color, in.world_position, camera.view_pos.xyz), 1.0);
+}
+#endif
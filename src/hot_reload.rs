@@ -0,0 +1,110 @@
+use anyhow::*;
+use std::{fs, path::PathBuf, time};
+
+/// Creates a `ShaderFile` for a WGSL file in src/, e.g.
+/// `shader_file!("shader.wgsl")`.
+macro_rules! shader_file {
+    ($name:literal) => {
+        $crate::hot_reload::ShaderFile::new(
+            concat!(env!("CARGO_MANIFEST_DIR"), "/src/", $name),
+            include_str!($name),
+        )
+    };
+}
+
+/// How often the watched files are checked for changes.
+const POLL_INTERVAL: time::Duration = time::Duration::from_millis(250);
+
+/// A WGSL shader that in debug builds is read from disk and watched for
+/// changes. Release builds only ever use the copy embedded in the binary.
+pub struct ShaderFile {
+    pub path: PathBuf,
+    pub source: String,
+    modified: Option<time::SystemTime>,
+    last_poll: time::Instant,
+}
+
+impl ShaderFile {
+    pub fn new(path: &str, embedded: &str) -> Self {
+        let mut shader = Self {
+            path: PathBuf::from(path),
+            source: embedded.to_string(),
+            modified: None,
+            last_poll: time::Instant::now(),
+        };
+
+        if Self::enabled() {
+            shader.modified = shader.modified_time();
+            match fs::read_to_string(&shader.path) {
+                Ok(source) => shader.source = source,
+                Err(e) => log::warn!("{}, using the embedded shader: {:?}", path, e),
+            }
+        }
+
+        shader
+    }
+
+    pub fn enabled() -> bool {
+        cfg!(debug_assertions)
+    }
+
+    pub fn name(&self) -> &str {
+        self.path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .unwrap_or_default()
+    }
+
+    fn modified_time(&self) -> Option<time::SystemTime> {
+        fs::metadata(&self.path).and_then(|m| m.modified()).ok()
+    }
+
+    /// Returns true when the file changed on disk, `source` is updated then.
+    /// It is up to the caller to `validate` it before making pipelines.
+    pub fn poll(&mut self) -> bool {
+        if !Self::enabled() || self.last_poll.elapsed() < POLL_INTERVAL {
+            return false;
+        }
+        self.last_poll = time::Instant::now();
+
+        let modified = self.modified_time();
+        if modified == self.modified {
+            return false;
+        }
+        self.modified = modified;
+
+        match fs::read_to_string(&self.path) {
+            Ok(source) => {
+                log::info!("Reloaded {}", self.path.display());
+                self.source = source;
+                true
+            }
+            Err(e) => {
+                log::error!("Could not read {}: {}", self.path.display(), e);
+                false
+            }
+        }
+    }
+}
+
+/// Parses and validates `source` the same way wgpu would, so a broken shader
+/// never reaches the device (which would panic).
+pub fn validate(source: &str, entry_points: &[&str]) -> Result<()> {
+    let module = naga::front::wgsl::parse_str(source)
+        .map_err(|e| anyhow!("{}", e.emit_to_string(source)))?;
+
+    naga::valid::Validator::new(
+        naga::valid::ValidationFlags::all(),
+        naga::valid::Capabilities::empty(),
+    )
+    .validate(&module)
+    .map_err(|e| anyhow!("{:?}", e))?;
+
+    for name in entry_points {
+        if !module.entry_points.iter().any(|entry| entry.name == *name) {
+            bail!("missing entry point {}", name);
+        }
+    }
+
+    Ok(())
+}
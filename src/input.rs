@@ -0,0 +1,738 @@
+use anyhow::*;
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write as _;
+use std::path::Path;
+use winit::event::{MouseButton, VirtualKeyCode};
+
+/// Something that triggers once per press.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Action {
+    Exit,
+    ReleaseCursor,
+    /// The next chord picks an action or axis direction, the one after that
+    /// becomes its new binding.
+    Rebind,
+    CycleToneMap,
+    ToggleLighting,
+    ToggleGizmos,
+    ToggleOcclusion,
+    CycleFog,
+    ToggleSky,
+    RaiseWater,
+    LowerWater,
+    CycleCameraMode,
+    /// Through the camera entities and back to the free camera.
+    CycleCamera,
+    ToggleGrass,
+    ToggleMapView,
+    ToggleReversedZ,
+    RecordPath,
+    PlayPath,
+    ToggleBloom,
+    /// One step earlier in the post processing chain, from the front to the
+    /// end.
+    MoveVignette,
+    DebugShaded,
+    DebugWireframe,
+    DebugNormals,
+    DebugUvs,
+    DebugDepth,
+    DebugOverdraw,
+}
+
+impl Action {
+    const ALL: [Action; 26] = [
+        Action::Exit,
+        Action::ReleaseCursor,
+        Action::Rebind,
+        Action::CycleToneMap,
+        Action::ToggleLighting,
+        Action::ToggleGizmos,
+        Action::ToggleOcclusion,
+        Action::CycleFog,
+        Action::ToggleSky,
+        Action::RaiseWater,
+        Action::LowerWater,
+        Action::CycleCameraMode,
+        Action::CycleCamera,
+        Action::ToggleGrass,
+        Action::ToggleMapView,
+        Action::ToggleReversedZ,
+        Action::RecordPath,
+        Action::PlayPath,
+        Action::ToggleBloom,
+        Action::MoveVignette,
+        Action::DebugShaded,
+        Action::DebugWireframe,
+        Action::DebugNormals,
+        Action::DebugUvs,
+        Action::DebugDepth,
+        Action::DebugOverdraw,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Action::Exit => "exit",
+            Action::ReleaseCursor => "release-cursor",
+            Action::Rebind => "rebind",
+            Action::CycleToneMap => "cycle-tone-map",
+            Action::ToggleLighting => "toggle-lighting",
+            Action::ToggleGizmos => "toggle-gizmos",
+            Action::ToggleOcclusion => "toggle-occlusion",
+            Action::CycleFog => "cycle-fog",
+            Action::ToggleSky => "toggle-sky",
+            Action::RaiseWater => "raise-water",
+            Action::LowerWater => "lower-water",
+            Action::CycleCameraMode => "cycle-camera-mode",
+            Action::CycleCamera => "cycle-camera",
+            Action::ToggleGrass => "toggle-grass",
+            Action::ToggleMapView => "toggle-map-view",
+            Action::ToggleReversedZ => "toggle-reversed-z",
+            Action::RecordPath => "record-path",
+            Action::PlayPath => "play-path",
+            Action::ToggleBloom => "toggle-bloom",
+            Action::MoveVignette => "move-vignette",
+            Action::DebugShaded => "debug-shaded",
+            Action::DebugWireframe => "debug-wireframe",
+            Action::DebugNormals => "debug-normals",
+            Action::DebugUvs => "debug-uvs",
+            Action::DebugDepth => "debug-depth",
+            Action::DebugOverdraw => "debug-overdraw",
+        }
+    }
+}
+
+/// Something held that goes from -1 to 1.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Axis {
+    MoveRight,
+    MoveForward,
+    MoveUp,
+    /// How fast to turn, unlike the mouse which turns by a distance.
+    LookRight,
+    LookUp,
+}
+
+impl Axis {
+    const ALL: [Axis; 5] = [
+        Axis::MoveRight,
+        Axis::MoveForward,
+        Axis::MoveUp,
+        Axis::LookRight,
+        Axis::LookUp,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Axis::MoveRight => "move-right",
+            Axis::MoveForward => "move-forward",
+            Axis::MoveUp => "move-up",
+            Axis::LookRight => "look-right",
+            Axis::LookUp => "look-up",
+        }
+    }
+}
+
+/// Gamepad sticks from -1 to 1, up and right positive, and triggers from 0
+/// to 1.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Analog {
+    LeftStickX,
+    LeftStickY,
+    RightStickX,
+    RightStickY,
+    LeftTrigger,
+    RightTrigger,
+}
+
+impl Analog {
+    pub const ALL: [Analog; 6] = [
+        Analog::LeftStickX,
+        Analog::LeftStickY,
+        Analog::RightStickX,
+        Analog::RightStickY,
+        Analog::LeftTrigger,
+        Analog::RightTrigger,
+    ];
+
+    /// `GamepadLeftStickX`...
+    pub fn name(self) -> String {
+        format!("Gamepad{:?}", self)
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|analog| analog.name() == name)
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum GamepadButton {
+    South,
+    East,
+    North,
+    West,
+    LeftBumper,
+    RightBumper,
+    Select,
+    Start,
+    LeftStick,
+    RightStick,
+    DPadUp,
+    DPadDown,
+    DPadLeft,
+    DPadRight,
+}
+
+impl GamepadButton {
+    const ALL: [GamepadButton; 14] = [
+        GamepadButton::South,
+        GamepadButton::East,
+        GamepadButton::North,
+        GamepadButton::West,
+        GamepadButton::LeftBumper,
+        GamepadButton::RightBumper,
+        GamepadButton::Select,
+        GamepadButton::Start,
+        GamepadButton::LeftStick,
+        GamepadButton::RightStick,
+        GamepadButton::DPadUp,
+        GamepadButton::DPadDown,
+        GamepadButton::DPadLeft,
+        GamepadButton::DPadRight,
+    ];
+}
+
+/// Keys that can be bound, named like their `VirtualKeyCode`.
+const KEYS: &[VirtualKeyCode] = {
+    use VirtualKeyCode::*;
+    &[
+        Key1, Key2, Key3, Key4, Key5, Key6, Key7, Key8, Key9, Key0, A, B, C, D, E, F, G, H, I, J,
+        K, L, M, N, O, P, Q, R, S, T, U, V, W, X, Y, Z, Escape, F1, F2, F3, F4, F5, F6, F7, F8, F9,
+        F10, F11, F12, Insert, Home, Delete, End, PageDown, PageUp, Left, Up, Right, Down, Back,
+        Return, Space, Tab, LAlt, LControl, LShift, RAlt, RControl, RShift, Comma, Period, Minus,
+        Equals, Slash, Backslash, Semicolon, Apostrophe, LBracket, RBracket, Grave,
+    ]
+};
+
+/// Anything that is either down or up.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Button {
+    Key(VirtualKeyCode),
+    Mouse(MouseButton),
+    Gamepad(GamepadButton),
+}
+
+impl Button {
+    /// `A`, `Space`, `MouseLeft`, `Mouse4`, `GamepadSouth`...
+    pub fn name(self) -> String {
+        match self {
+            Button::Key(key) => format!("{:?}", key),
+            Button::Mouse(MouseButton::Other(n)) => format!("Mouse{}", n),
+            Button::Mouse(button) => format!("Mouse{:?}", button),
+            Button::Gamepad(button) => format!("Gamepad{:?}", button),
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        if let Some(button) = name.strip_prefix("Gamepad") {
+            return GamepadButton::ALL
+                .iter()
+                .find(|b| format!("{:?}", b) == button)
+                .map(|&b| Button::Gamepad(b));
+        }
+        if let Some(button) = name.strip_prefix("Mouse") {
+            let button = match button {
+                "Left" => MouseButton::Left,
+                "Right" => MouseButton::Right,
+                "Middle" => MouseButton::Middle,
+                other => MouseButton::Other(other.parse().ok()?),
+            };
+            return Some(Button::Mouse(button));
+        }
+        KEYS.iter()
+            .find(|key| format!("{:?}", key) == name)
+            .map(|&key| Button::Key(key))
+    }
+}
+
+/// Buttons that all have to be down, e.g. `LControl + S`.
+pub type Chord = Vec<Button>;
+
+/// One direction of an axis.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Side {
+    /// 1 while down.
+    Chord(Chord),
+    /// How far a stick is pushed that way or a trigger is pulled.
+    Analog(Analog),
+}
+
+impl Side {
+    fn name(&self) -> String {
+        match self {
+            Side::Chord(chord) => chord_name(chord),
+            Side::Analog(analog) => analog.name(),
+        }
+    }
+
+    fn parse(text: &str) -> Result<Self> {
+        match Analog::from_name(text.trim()) {
+            Some(analog) => Ok(Side::Analog(analog)),
+            None => parse_chord(text).map(Side::Chord),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum AxisBinding {
+    /// Negative and positive.
+    Pair(Side, Side),
+    /// A stick's full range, `-` in front flips it.
+    Analog { analog: Analog, inverted: bool },
+}
+
+impl AxisBinding {
+    fn name(&self) -> String {
+        match self {
+            AxisBinding::Pair(negative, positive) => {
+                format!("{} / {}", negative.name(), positive.name())
+            }
+            AxisBinding::Analog { analog, inverted } => {
+                format!("{}{}", if *inverted { "-" } else { "" }, analog.name())
+            }
+        }
+    }
+
+    fn parse(text: &str) -> Result<Self> {
+        if let Some((negative, positive)) = text.split_once('/') {
+            return Ok(AxisBinding::Pair(
+                Side::parse(negative)?,
+                Side::parse(positive)?,
+            ));
+        }
+        let (name, inverted) = match text.strip_prefix('-') {
+            Some(name) => (name, true),
+            None => (text, false),
+        };
+        let analog = Analog::from_name(name.trim())
+            .ok_or_else(|| anyhow!("expected negative / positive or a stick, got {:?}", text))?;
+        Ok(AxisBinding::Analog { analog, inverted })
+    }
+}
+
+fn chord_name(chord: &[Button]) -> String {
+    chord
+        .iter()
+        .map(|button| button.name())
+        .collect::<Vec<_>>()
+        .join(" + ")
+}
+
+fn parse_chord(text: &str) -> Result<Chord> {
+    text.split('+')
+        .map(|name| {
+            let name = name.trim();
+            Button::from_name(name).ok_or_else(|| anyhow!("unknown button {:?}", name))
+        })
+        .collect()
+}
+
+const DEFAULT_BINDINGS: &str = "\
+exit = LControl + Q
+release-cursor = Escape
+rebind = F9
+cycle-tone-map = T
+toggle-lighting = L
+toggle-gizmos = G
+toggle-occlusion = O
+cycle-fog = F
+toggle-sky = K
+raise-water = PageUp, GamepadDPadUp
+lower-water = PageDown, GamepadDPadDown
+cycle-camera-mode = C, GamepadNorth
+cycle-camera = X
+toggle-grass = V, GamepadWest
+toggle-map-view = M, GamepadSelect
+toggle-reversed-z = Z
+record-path = R
+play-path = P, GamepadStart
+toggle-bloom = B
+move-vignette = N
+debug-shaded = F1
+debug-wireframe = F2
+debug-normals = F3
+debug-uvs = F4
+debug-depth = F5
+debug-overdraw = F6
+move-right = A / D, Left / Right, GamepadLeftStickX
+move-forward = S / W, Down / Up, GamepadLeftStickY
+move-up = LShift / Space, GamepadLeftTrigger / GamepadRightTrigger
+look-right = GamepadRightStickX
+look-up = GamepadRightStickY
+";
+
+/// What every action and axis is bound to. Saved as text, one action or
+/// axis per line:
+///
+/// ```text
+/// # action = chord, ...
+/// exit = Escape
+/// toggle-map-view = M, LControl + MouseRight
+/// # axis = negative / positive or stick, ...
+/// move-forward = S / W, Down / Up, GamepadLeftStickY
+/// move-up = LShift / Space, GamepadLeftTrigger / GamepadRightTrigger
+/// ```
+///
+/// A chord is buttons joined by `+` that have to be down together. `#`
+/// starts a comment, actions and axes left out aren't bound.
+#[derive(Debug, Default, Clone)]
+pub struct Bindings {
+    pub actions: HashMap<Action, Vec<Chord>>,
+    pub axes: HashMap<Axis, Vec<AxisBinding>>,
+}
+
+impl Bindings {
+    /// What's bound without a config file.
+    pub fn defaults() -> Self {
+        Self::parse(DEFAULT_BINDINGS).expect("default bindings")
+    }
+
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut bindings = Self::default();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let (name, value) = line
+                .split_once('=')
+                .ok_or_else(|| anyhow!("Line {}: expected name = bindings", number + 1))?;
+            let name = name.trim();
+            let chords = value.split(',').map(str::trim).filter(|c| !c.is_empty());
+
+            if let Some(&action) = Action::ALL.iter().find(|a| a.name() == name) {
+                let chords = chords
+                    .map(parse_chord)
+                    .collect::<Result<Vec<_>>>()
+                    .with_context(|| format!("Line {}", number + 1))?;
+                bindings.actions.insert(action, chords);
+            } else if let Some(&axis) = Axis::ALL.iter().find(|a| a.name() == name) {
+                let axis_bindings = chords
+                    .map(AxisBinding::parse)
+                    .collect::<Result<Vec<_>>>()
+                    .with_context(|| format!("Line {}", number + 1))?;
+                bindings.axes.insert(axis, axis_bindings);
+            } else {
+                bail!("Line {}: unknown action or axis {:?}", number + 1, name);
+            }
+        }
+        Ok(bindings)
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("Reading bindings {}", path.display()))?;
+        Self::parse(&text).with_context(|| format!("Parsing bindings {}", path.display()))
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut text = String::from("# action = chord, ...\n");
+        for action in Action::ALL.iter() {
+            if let Some(chords) = self.actions.get(action) {
+                let chords: Vec<String> = chords.iter().map(|c| chord_name(c)).collect();
+                let _ = writeln!(text, "{} = {}", action.name(), chords.join(", "));
+            }
+        }
+        text.push_str("# axis = negative / positive or stick, ...\n");
+        for axis in Axis::ALL.iter() {
+            if let Some(axis_bindings) = self.axes.get(axis) {
+                let names: Vec<String> = axis_bindings.iter().map(AxisBinding::name).collect();
+                let _ = writeln!(text, "{} = {}", axis.name(), names.join(", "));
+            }
+        }
+        text
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let path = path.as_ref();
+        std::fs::write(path, self.to_text())
+            .with_context(|| format!("Writing bindings {}", path.display()))
+    }
+}
+
+#[derive(Debug)]
+enum Rebinding {
+    /// Waiting for a chord of the action or axis direction to rebind.
+    Pick,
+    /// Waiting for the new chord of this action.
+    Chord(Action),
+    /// Waiting for the new chord of one side of `axis`'s `index`th binding.
+    Side {
+        axis: Axis,
+        index: usize,
+        positive: bool,
+    },
+}
+
+/// Turns button presses into actions and axes through `Bindings`.
+#[derive(Debug)]
+pub struct Input {
+    pub bindings: Bindings,
+    down: HashSet<Button>,
+    analog: HashMap<Analog, f32>,
+    rebinding: Option<Rebinding>,
+    // Buttons pressed since rebinding started waiting for a chord
+    captured: Chord,
+}
+
+impl Input {
+    pub fn new(bindings: Bindings) -> Self {
+        Self {
+            bindings,
+            down: HashSet::new(),
+            analog: HashMap::new(),
+            rebinding: None,
+            captured: Vec::new(),
+        }
+    }
+
+    fn chord_down(&self, chord: &[Button]) -> bool {
+        !chord.is_empty() && chord.iter().all(|button| self.down.contains(button))
+    }
+
+    /// Records a press or release, returning the actions it triggered. Of
+    /// the chords `button` completes only the longest count, so `LControl +
+    /// S` doesn't also trigger what `S` is bound to.
+    pub fn process(&mut self, button: Button, pressed: bool) -> Vec<Action> {
+        if !pressed {
+            self.down.remove(&button);
+            if self.rebinding.is_some() && !self.captured.is_empty() {
+                let chord = std::mem::take(&mut self.captured);
+                return self.finish_chord(chord);
+            }
+            return Vec::new();
+        }
+        // Key repeat
+        if !self.down.insert(button) {
+            return Vec::new();
+        }
+        if self.rebinding.is_some() {
+            self.captured.push(button);
+            return Vec::new();
+        }
+
+        self.completed_actions(button)
+    }
+
+    fn completed_actions(&self, button: Button) -> Vec<Action> {
+        let mut longest = 0;
+        let mut actions = Vec::new();
+        for (&action, chords) in self.bindings.actions.iter() {
+            for chord in chords {
+                if !chord.contains(&button) || !self.chord_down(chord) || chord.len() < longest {
+                    continue;
+                }
+                if chord.len() > longest {
+                    longest = chord.len();
+                    actions.clear();
+                }
+                if !actions.contains(&action) {
+                    actions.push(action);
+                }
+            }
+        }
+        actions
+    }
+
+    fn finish_chord(&mut self, chord: Chord) -> Vec<Action> {
+        match self.rebinding.take() {
+            Some(Rebinding::Pick) => {
+                let same =
+                    |c: &Chord| c.len() == chord.len() && c.iter().all(|b| chord.contains(b));
+                let action = self
+                    .bindings
+                    .actions
+                    .iter()
+                    .find_map(|(&action, chords)| chords.iter().any(same).then_some(action));
+                if let Some(action) = action {
+                    log::info!("Press the new chord for {}", action.name());
+                    self.rebinding = Some(Rebinding::Chord(action));
+                } else if let Some((axis, index, positive)) = self.axis_side(same) {
+                    log::info!(
+                        "Press the new chord for {} {}",
+                        axis.name(),
+                        if positive { "positive" } else { "negative" }
+                    );
+                    self.rebinding = Some(Rebinding::Side {
+                        axis,
+                        index,
+                        positive,
+                    });
+                } else {
+                    log::info!("{} isn't bound to anything", chord_name(&chord));
+                }
+            }
+            Some(Rebinding::Chord(action)) => {
+                log::info!("{} = {}", action.name(), chord_name(&chord));
+                self.bindings.actions.insert(action, vec![chord]);
+            }
+            Some(Rebinding::Side {
+                axis,
+                index,
+                positive,
+            }) => {
+                let binding = self
+                    .bindings
+                    .axes
+                    .get_mut(&axis)
+                    .and_then(|bindings| bindings.get_mut(index));
+                if let Some(binding) = binding {
+                    if let AxisBinding::Pair(negative, positive_side) = binding {
+                        let side = if positive { positive_side } else { negative };
+                        *side = Side::Chord(chord);
+                    }
+                    log::info!("{} = {}", axis.name(), binding.name());
+                }
+            }
+            None => {}
+        }
+        Vec::new()
+    }
+
+    /// The axis, binding index and side that `matches` one of the chords
+    /// of, true for the positive side.
+    fn axis_side(&self, matches: impl Fn(&Chord) -> bool) -> Option<(Axis, usize, bool)> {
+        self.bindings.axes.iter().find_map(|(&axis, bindings)| {
+            bindings
+                .iter()
+                .enumerate()
+                .find_map(|(index, binding)| match binding {
+                    AxisBinding::Pair(Side::Chord(c), _) if matches(c) => {
+                        Some((axis, index, false))
+                    }
+                    AxisBinding::Pair(_, Side::Chord(c)) if matches(c) => Some((axis, index, true)),
+                    _ => None,
+                })
+        })
+    }
+
+    /// Starts rebinding: the next chord picks an action or one direction of
+    /// an axis by one of its current chords, the one after that replaces
+    /// them. An action loses its other chords, an axis direction only the
+    /// one picked.
+    pub fn start_rebinding(&mut self) {
+        log::info!("Press the chord of the action or axis direction to rebind");
+        self.rebinding = Some(Rebinding::Pick);
+        self.captured.clear();
+    }
+
+    pub fn rebinding(&self) -> bool {
+        self.rebinding.is_some()
+    }
+
+    /// Lets go of every button, stick and trigger, e.g. when the window
+    /// loses focus and won't see them being released.
+    pub fn release_all(&mut self) {
+        self.down.clear();
+        self.analog.clear();
+        self.captured.clear();
+    }
+
+    /// Sets a stick or trigger, after its dead zone and curve.
+    pub fn set_analog(&mut self, analog: Analog, value: f32) {
+        self.analog.insert(analog, value);
+    }
+
+    fn analog(&self, analog: Analog) -> f32 {
+        self.analog.get(&analog).copied().unwrap_or(0.0)
+    }
+
+    fn side(&self, side: &Side) -> f32 {
+        match side {
+            Side::Chord(chord) => {
+                if self.chord_down(chord) {
+                    1.0
+                } else {
+                    0.0
+                }
+            }
+            Side::Analog(analog) => self.analog(*analog).max(0.0),
+        }
+    }
+
+    /// Everything bound to `axis` added up, clamped to -1 to 1. Holding
+    /// both directions cancels out.
+    pub fn axis(&self, axis: Axis) -> f32 {
+        let axis_bindings = match self.bindings.axes.get(&axis) {
+            Some(axis_bindings) => axis_bindings,
+            None => return 0.0,
+        };
+        let sum: f32 = axis_bindings
+            .iter()
+            .map(|binding| match binding {
+                AxisBinding::Pair(negative, positive) => self.side(positive) - self.side(negative),
+                AxisBinding::Analog { analog, inverted } => {
+                    let value = self.analog(*analog);
+                    if *inverted {
+                        -value
+                    } else {
+                        value
+                    }
+                }
+            })
+            .sum();
+        sum.clamp(-1.0, 1.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tap(input: &mut Input, key: VirtualKeyCode) {
+        input.process(Button::Key(key), true);
+        input.process(Button::Key(key), false);
+    }
+
+    #[test]
+    fn rebinds_an_axis_direction() {
+        let mut input = Input::new(Bindings::defaults());
+        input.start_rebinding();
+        tap(&mut input, VirtualKeyCode::W);
+        assert!(input.rebinding());
+        tap(&mut input, VirtualKeyCode::I);
+        assert!(!input.rebinding());
+
+        input.process(Button::Key(VirtualKeyCode::W), true);
+        assert_eq!(input.axis(Axis::MoveForward), 0.0);
+        input.process(Button::Key(VirtualKeyCode::W), false);
+        input.process(Button::Key(VirtualKeyCode::I), true);
+        assert_eq!(input.axis(Axis::MoveForward), 1.0);
+        input.process(Button::Key(VirtualKeyCode::I), false);
+        // The other direction and binding stay
+        input.process(Button::Key(VirtualKeyCode::S), true);
+        assert_eq!(input.axis(Axis::MoveForward), -1.0);
+        input.process(Button::Key(VirtualKeyCode::S), false);
+        input.process(Button::Key(VirtualKeyCode::Up), true);
+        assert_eq!(input.axis(Axis::MoveForward), 1.0);
+    }
+
+    #[test]
+    fn release_all_stops_movement() {
+        let mut input = Input::new(Bindings::defaults());
+        input.process(Button::Key(VirtualKeyCode::W), true);
+        input.set_analog(Analog::LeftStickX, 1.0);
+        input.release_all();
+        assert_eq!(input.axis(Axis::MoveForward), 0.0);
+        assert_eq!(input.axis(Axis::MoveRight), 0.0);
+
+        // Pressing it again counts as a new press
+        input.process(Button::Key(VirtualKeyCode::W), true);
+        assert_eq!(input.axis(Axis::MoveForward), 1.0);
+    }
+}
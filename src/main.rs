@@ -5,27 +5,76 @@ use winit::{
     window::WindowBuilder,
 };
 
+mod atmosphere;
 mod camera;
+mod camera_path;
+mod culling;
+mod debug_draw;
+mod debug_view;
+mod ecs;
+mod game;
+mod gamepad;
+mod geometry;
+mod grass;
+#[macro_use]
+mod hot_reload;
+mod input;
 mod mesh;
 mod meshgen;
+mod occlusion;
+mod passes;
+mod pipeline;
+mod postprocess;
+mod preprocessor;
+mod render_graph;
 mod state;
 mod texture;
+mod timestep;
+mod transparency;
+mod water;
+
+// 1 disables MSAA, 4 is the only other count every adapter supports
+const MSAA_SAMPLES: u32 = 4;
+// Depth from 1 near to 0 at infinity instead of 0 near to 1 far, Z toggles
+const REVERSED_Z: bool = false;
+// Simulation steps per second, rendering interpolates between them
+const TICK_RATE: f64 = 60.0;
 
 fn main() {
     env_logger::init();
     let event_loop = EventLoop::new();
     let window = WindowBuilder::new().build(&event_loop).unwrap();
-    window.set_cursor_visible(false);
-    window.set_cursor_grab(true).unwrap();
 
-    let mut state = pollster::block_on(state::State::new(&window));
-    let mut curr_time = time::Instant::now();
+    let mut state = pollster::block_on(state::State::new(
+        &window,
+        MSAA_SAMPLES,
+        REVERSED_Z,
+        TICK_RATE,
+    ));
+    state.apply_cursor(&window);
 
-    event_loop.run(move |event, _, control_flow| {
-        state.delta_time = curr_time.elapsed();
-        curr_time = time::Instant::now();
+    // `--play path.txt` flies the camera along a recorded path at a fixed
+    // 60 steps per second, the same frames every run
+    let mut args = std::env::args().skip_while(|arg| arg != "--play");
+    if let Some(path) = args.nth(1) {
+        match camera_path::CameraPath::load(&path) {
+            Ok(path) => state.play(path, Some(time::Duration::from_secs_f64(1.0 / 60.0))),
+            Err(e) => log::error!("{:?}", e),
+        }
+    }
+    // `--virtual-gamepad script.txt` plays back gamepad input, see
+    // `gamepad::VirtualGamepad`
+    let mut args = std::env::args().skip_while(|arg| arg != "--virtual-gamepad");
+    if let Some(path) = args.nth(1) {
+        match gamepad::VirtualGamepad::load(&path) {
+            Ok(gamepad) => state.gamepads.devices.push(Box::new(gamepad)),
+            Err(e) => log::error!("{:?}", e),
+        }
+    }
 
+    event_loop.run(move |event, _, control_flow| {
         *control_flow = state.input(&event);
+        state.apply_cursor(&window);
         match event {
             Event::MainEventsCleared => {
                 state.update();
@@ -1,8 +1,7 @@
-use anyhow::*;
-use wgpu::util::DeviceExt;
-
-use crate::state;
+use crate::culling;
+use crate::geometry;
 use crate::texture;
+use std::ops::Range;
 
 #[repr(C)]
 #[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
@@ -12,24 +11,82 @@ pub struct Vertex {
     pub uv: [f32; 2],
 }
 
+impl Vertex {
+    pub fn layout<'a>() -> wgpu::VertexBufferLayout<'a> {
+        const ATTRIBUTES: [wgpu::VertexAttribute; 3] = wgpu::vertex_attr_array![
+            0 => Float32x3, // Position
+            1 => Float32x3, // Normal
+            2 => Float32x2  // UV
+        ];
+
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<Vertex>() as u64,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: &ATTRIBUTES,
+        }
+    }
+}
+
+/// Per instance data for the INSTANCED shader variant, a model matrix and a
+/// color the texture is multiplied with.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct Instance {
+    pub model: [[f32; 4]; 4],
+    pub color: [f32; 4],
+}
+
+impl Instance {
+    pub fn layout<'a>() -> wgpu::VertexBufferLayout<'a> {
+        const ATTRIBUTES: [wgpu::VertexAttribute; 5] = wgpu::vertex_attr_array![
+            3 => Float32x4, // Model
+            4 => Float32x4,
+            5 => Float32x4,
+            6 => Float32x4,
+            7 => Float32x4  // Color
+        ];
+
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<Instance>() as u64,
+            step_mode: wgpu::VertexStepMode::Instance,
+            attributes: &ATTRIBUTES,
+        }
+    }
+}
+
 pub struct Descriptor {
     pub vertices: Vec<[f32; 3]>,
     pub normals: Vec<[f32; 3]>,
     pub uvs: Vec<[f32; 2]>,
     pub triangles: Vec<u32>,
+    /// Ranges of `triangles` that get their own bounds and can be culled on
+    /// their own, e.g. terrain chunks. Empty means one for the whole mesh.
+    pub submeshes: Vec<Range<u32>>,
     pub texture: texture::Texture,
 }
 
+/// Part of a mesh's index buffer with its bounds.
+pub struct Submesh {
+    pub indices: Range<u32>,
+    pub bounds: culling::Bounds,
+}
+
 pub struct Mesh {
-    pub vertices: wgpu::Buffer,
-    pub indices: wgpu::Buffer,
-    pub indices_count: u32,
+    /// Where the mesh's indices are in the `GeometryPool`.
+    pub indices: Range<u32>,
     pub texture: wgpu::BindGroup,
     pub texture_layout: wgpu::BindGroupLayout,
+    pub bounds: culling::Bounds,
+    pub submeshes: Vec<Submesh>,
 }
 
 impl Descriptor {
-    pub fn bake(&self, device: &wgpu::Device) -> Mesh {
+    pub fn bake(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        pool: &mut geometry::GeometryPool,
+    ) -> Mesh {
         // TODO: Currently sending an array of structs, could send various arrays
         // (more than one buffer) as we already are storing in that format.
         // Need to change how vertex attributes are declared.
@@ -49,19 +106,7 @@ impl Descriptor {
             })
             .collect::<Vec<Vertex>>();
 
-        let vertices = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: Some("Vertex Buffer"),
-            usage: wgpu::BufferUsages::VERTEX,
-            contents: bytemuck::cast_slice(&vertices_with_attributes[..]),
-        });
-
-        let indices = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: Some("Index Buffer"),
-            usage: wgpu::BufferUsages::INDEX,
-            contents: bytemuck::cast_slice(&self.triangles[..]),
-        });
-
-        let indices_count = self.triangles.len() as u32;
+        let indices = pool.add(device, queue, &vertices_with_attributes, &self.triangles);
 
         let texture_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
             entries: &[
@@ -103,72 +148,91 @@ impl Descriptor {
             label: Some("Texture Bind Group"),
         });
 
+        let bounds = culling::Bounds::from_points(self.vertices.iter().copied());
+        let submeshes = if self.submeshes.is_empty() {
+            vec![Submesh {
+                indices: indices.clone(),
+                bounds,
+            }]
+        } else {
+            self.submeshes
+                .iter()
+                .map(|range| Submesh {
+                    indices: indices.start + range.start..indices.start + range.end,
+                    bounds: culling::Bounds::from_points(
+                        self.triangles[range.start as usize..range.end as usize]
+                            .iter()
+                            .map(|&index| self.vertices[index as usize]),
+                    ),
+                })
+                .collect()
+        };
+
         Mesh {
-            vertices,
             indices,
-            indices_count,
             texture,
             texture_layout,
+            bounds,
+            submeshes,
         }
     }
 }
 
-// TODO: Change to something useful (need to pass self as parameter at least!!).
-// We are currently using the surface, the device and the camera bind group (uniform).
-// So we would need to abstract that away for it to work...
 impl Mesh {
-    pub fn draw(state: &state::State) -> Result<()> {
-        let frame = state.surface.get_current_frame()?.output;
-
-        let mut encoder = state
-            .device
-            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
-                label: Some("Mesh Encoder"),
-            });
-
-        {
-            let view = frame
-                .texture
-                .create_view(&wgpu::TextureViewDescriptor::default());
-
-            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-                label: Some("Mesh Render Pass"),
-                color_attachments: &[wgpu::RenderPassColorAttachment {
-                    view: &view,
-                    resolve_target: None,
-                    ops: wgpu::Operations {
-                        load: wgpu::LoadOp::Clear(state.clear_color),
-                        store: true,
-                    },
-                }],
-                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
-                    view: &state.depth_texture.view,
-                    depth_ops: Some(wgpu::Operations {
-                        load: wgpu::LoadOp::Clear(1.0),
-                        store: true,
-                    }),
-                    stencil_ops: None,
-                }),
-            });
-
-            render_pass.set_pipeline(&state.render_pipeline);
+    /// Indices into `submeshes` of the ones at least partly inside `frustum`.
+    pub fn visible(&self, frustum: &culling::Frustum, stats: &mut culling::Stats) -> Vec<usize> {
+        if !frustum.intersects(&self.bounds) {
+            stats.culled += self.submeshes.len() as u32;
+            return Vec::new();
+        }
 
-            // Textures
-            render_pass.set_bind_group(0, &state.mesh.texture, &[]);
+        let visible: Vec<usize> = (0..self.submeshes.len())
+            .filter(|&i| frustum.intersects(&self.submeshes[i].bounds))
+            .collect();
+        stats.drawn += visible.len() as u32;
+        stats.culled += (self.submeshes.len() - visible.len()) as u32;
+        visible
+    }
 
-            // Uniforms
-            render_pass.set_bind_group(1, &state.camera_bind_group, &[]);
+    /// Adds a draw for each of `submeshes` to `list`, neighbouring ones
+    /// merged into one.
+    pub fn draw_commands(&self, submeshes: &[usize], list: &mut geometry::DrawList) {
+        let mut range: Option<Range<u32>> = None;
+        for &i in submeshes {
+            let indices = &self.submeshes[i].indices;
+            range = match range {
+                Some(range) if range.end == indices.start => Some(range.start..indices.end),
+                Some(range) => {
+                    list.push(range);
+                    Some(indices.clone())
+                }
+                None => Some(indices.clone()),
+            };
+        }
 
-            // Vertices
-            render_pass.set_vertex_buffer(0, state.mesh.vertices.slice(..));
+        if let Some(range) = range {
+            list.push(range);
+        }
+    }
 
-            // Indices
-            render_pass.set_index_buffer(state.mesh.indices.slice(..), wgpu::IndexFormat::Uint32);
+    /// Binds the texture (group 0). The pool, pipeline and other bind groups
+    /// are up to the caller.
+    pub fn bind<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) {
+        render_pass.set_bind_group(0, &self.texture, &[]);
+    }
+}
 
-            render_pass.draw_indexed(0..state.mesh.indices_count, 0, 0..1);
+/// Runs of consecutive submesh indices, e.g. `[1, 2, 3, 7]` gives `1..4` and
+/// `7..8`. Each run is one multi draw out of a buffer with a command per
+/// submesh, like `OcclusionCuller::draws`.
+pub fn runs(submeshes: &[usize]) -> Vec<Range<u32>> {
+    let mut runs: Vec<Range<u32>> = Vec::new();
+    for &i in submeshes {
+        let i = i as u32;
+        match runs.last_mut() {
+            Some(run) if run.end == i => run.end = i + 1,
+            _ => runs.push(i..i + 1),
         }
-
-        state.queue.submit(std::iter::once(encoder.finish()));
-        Ok(())
     }
+    runs
 }
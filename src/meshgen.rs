@@ -3,6 +3,10 @@ use crate::texture;
 use image::{GenericImageView, Pixel};
 use std::vec::Vec;
 
+/// Terrain is split into chunks of this many quads along each side, so parts
+/// of it can be culled.
+const CHUNK_SIZE: i32 = 32;
+
 impl mesh::Descriptor {
     pub fn from_height_map(
         map: &image::DynamicImage,
@@ -40,17 +44,24 @@ impl mesh::Descriptor {
             }
         }
 
-        for x in 0..(columns - 1) {
-            for y in 0..(rows - 1) {
-                // first  triangle: [      y + x * rows, (y + 1) + x * rows,       y + (x + 1) * rows]
-                triangles.push((y + x * rows) as u32);
-                triangles.push((y + 1 + x * rows) as u32);
-                triangles.push((y + (x + 1) * rows) as u32);
-
-                // second triangle: [(y + 1) + x * rows, (y + 1) + (x + 1) * rows, y + (x + 1) * rows]
-                triangles.push((y + 1 + x * rows) as u32);
-                triangles.push((y + 1 + (x + 1) * rows) as u32);
-                triangles.push((y + (x + 1) * rows) as u32);
+        let mut submeshes = Vec::new();
+        for chunk_x in (0..(columns - 1)).step_by(CHUNK_SIZE as usize) {
+            for chunk_y in (0..(rows - 1)).step_by(CHUNK_SIZE as usize) {
+                let start = triangles.len() as u32;
+                for x in chunk_x..(chunk_x + CHUNK_SIZE).min(columns - 1) {
+                    for y in chunk_y..(chunk_y + CHUNK_SIZE).min(rows - 1) {
+                        // first  triangle: [      y + x * rows, (y + 1) + x * rows,       y + (x + 1) * rows]
+                        triangles.push((y + x * rows) as u32);
+                        triangles.push((y + 1 + x * rows) as u32);
+                        triangles.push((y + (x + 1) * rows) as u32);
+
+                        // second triangle: [(y + 1) + x * rows, (y + 1) + (x + 1) * rows, y + (x + 1) * rows]
+                        triangles.push((y + 1 + x * rows) as u32);
+                        triangles.push((y + 1 + (x + 1) * rows) as u32);
+                        triangles.push((y + (x + 1) * rows) as u32);
+                    }
+                }
+                submeshes.push(start..triangles.len() as u32);
             }
         }
 
@@ -59,7 +70,63 @@ impl mesh::Descriptor {
             normals,
             uvs,
             triangles,
+            submeshes,
             texture,
         }
     }
 }
+
+/// Heights of a `from_height_map` mesh, for keeping things on its surface.
+pub struct HeightField {
+    columns: i32,
+    rows: i32,
+    origin: (f32, f32),
+    // World size of a grid cell along x and z
+    cell: (f32, f32),
+    heights: Vec<f32>,
+}
+
+impl HeightField {
+    /// `columns` and `rows` have to be the ones the mesh was made with.
+    pub fn from_terrain(terrain: &mesh::Descriptor, columns: i32, rows: i32) -> Self {
+        let first = terrain.vertices[0];
+        let next_column = terrain.vertices[rows as usize];
+        let next_row = terrain.vertices[1];
+
+        Self {
+            columns,
+            rows,
+            origin: (first[0], first[2]),
+            cell: (next_column[0] - first[0], next_row[2] - first[2]),
+            heights: terrain.vertices.iter().map(|vertex| vertex[1]).collect(),
+        }
+    }
+
+    fn at(&self, x: i32, y: i32) -> f32 {
+        self.heights[(y + x * self.rows) as usize]
+    }
+
+    /// Height of the surface at `x`, `z`, on the same triangles the mesh is
+    /// made of. Clamped to the edges outside of it.
+    pub fn height(&self, x: f32, z: f32) -> f32 {
+        let u = ((x - self.origin.0) / self.cell.0).clamp(0.0, (self.columns - 1) as f32);
+        let v = ((z - self.origin.1) / self.cell.1).clamp(0.0, (self.rows - 1) as f32);
+        let (cx, cy) = (
+            (u as i32).min(self.columns - 2),
+            (v as i32).min(self.rows - 2),
+        );
+        let (fx, fy) = (u - cx as f32, v - cy as f32);
+
+        let h00 = self.at(cx, cy);
+        let h10 = self.at(cx + 1, cy);
+        let h01 = self.at(cx, cy + 1);
+        let h11 = self.at(cx + 1, cy + 1);
+
+        // Quads are split along the diagonal from (x + 1, y) to (x, y + 1)
+        if fx + fy <= 1.0 {
+            h00 + (h10 - h00) * fx + (h01 - h00) * fy
+        } else {
+            h11 + (h01 - h11) * (1.0 - fx) + (h10 - h11) * (1.0 - fy)
+        }
+    }
+}
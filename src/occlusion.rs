@@ -0,0 +1,510 @@
+use anyhow::*;
+use std::num::NonZeroU32;
+use wgpu::util::DeviceExt;
+
+use crate::geometry;
+use crate::mesh;
+use crate::preprocessor::{Defines, Preprocessor};
+use crate::state;
+use crate::texture;
+
+const HIZ_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::R32Float;
+
+/// A submesh as the cull shader sees it.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct Object {
+    min: [f32; 4],
+    max: [f32; 4],
+    first_index: u32,
+    index_count: u32,
+    _padding: [u32; 2],
+}
+
+struct Pipelines {
+    copy: wgpu::ComputePipeline,
+    downsample: wgpu::ComputePipeline,
+    cull: wgpu::ComputePipeline,
+}
+
+/// GPU occlusion culling of a mesh's submeshes. Builds a hierarchical depth
+/// pyramid out of the opaque scene's depth, before the water writes to it,
+/// and tests every submesh's box against it the next frame, writing one DrawIndexedIndirect per submesh
+/// into `draws` (zero indices when hidden). Boxes are projected with the
+/// camera that depth was rendered with, see `set_previous_camera`.
+///
+/// Only the terrain is culled like this, instanced objects are frustum
+/// culled on the CPU by `transparency::Objects`.
+pub struct OcclusionCuller {
+    pub enabled: bool,
+    pub draws: wgpu::Buffer,
+    // The pyramid is garbage after creation and resizes
+    pub depth_valid: bool,
+    multisampled: bool,
+    reversed_z: bool,
+    object_count: u32,
+    objects: wgpu::Buffer,
+    previous_camera: wgpu::Buffer,
+    pipelines: Pipelines,
+    copy_layout: wgpu::BindGroupLayout,
+    downsample_layout: wgpu::BindGroupLayout,
+    cull_layout: wgpu::BindGroupLayout,
+    mip_sizes: Vec<(u32, u32)>,
+    copy_group: wgpu::BindGroup,
+    downsample_groups: Vec<wgpu::BindGroup>,
+    cull_group: wgpu::BindGroup,
+}
+
+fn texture_entry(
+    binding: u32,
+    sample_type: wgpu::TextureSampleType,
+    multisampled: bool,
+) -> wgpu::BindGroupLayoutEntry {
+    wgpu::BindGroupLayoutEntry {
+        binding,
+        visibility: wgpu::ShaderStages::COMPUTE,
+        ty: wgpu::BindingType::Texture {
+            multisampled,
+            sample_type,
+            view_dimension: wgpu::TextureViewDimension::D2,
+        },
+        count: None,
+    }
+}
+
+fn storage_texture_entry(binding: u32) -> wgpu::BindGroupLayoutEntry {
+    wgpu::BindGroupLayoutEntry {
+        binding,
+        visibility: wgpu::ShaderStages::COMPUTE,
+        ty: wgpu::BindingType::StorageTexture {
+            access: wgpu::StorageTextureAccess::WriteOnly,
+            format: HIZ_FORMAT,
+            view_dimension: wgpu::TextureViewDimension::D2,
+        },
+        count: None,
+    }
+}
+
+fn buffer_entry(binding: u32, ty: wgpu::BufferBindingType) -> wgpu::BindGroupLayoutEntry {
+    wgpu::BindGroupLayoutEntry {
+        binding,
+        visibility: wgpu::ShaderStages::COMPUTE,
+        ty: wgpu::BindingType::Buffer {
+            ty,
+            has_dynamic_offset: false,
+            min_binding_size: None,
+        },
+        count: None,
+    }
+}
+
+fn workgroups(size: u32, workgroup_size: u32) -> u32 {
+    size.div_ceil(workgroup_size)
+}
+
+impl OcclusionCuller {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        device: &wgpu::Device,
+        shaders: &mut Preprocessor,
+        depth: &texture::Texture,
+        sample_count: u32,
+        reversed_z: bool,
+        width: u32,
+        height: u32,
+        mesh: &mesh::Mesh,
+    ) -> Result<Self> {
+        let multisampled = sample_count > 1;
+
+        let objects: Vec<Object> = mesh
+            .submeshes
+            .iter()
+            .map(|submesh| {
+                let aabb = submesh.bounds.aabb;
+                Object {
+                    min: aabb.min.to_homogeneous().into(),
+                    max: aabb.max.to_homogeneous().into(),
+                    first_index: submesh.indices.start,
+                    index_count: submesh.indices.end - submesh.indices.start,
+                    _padding: [0; 2],
+                }
+            })
+            .collect();
+
+        let object_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Occlusion Object Buffer"),
+            contents: bytemuck::cast_slice(&objects),
+            usage: wgpu::BufferUsages::STORAGE,
+        });
+
+        let previous_camera = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Occlusion Previous Camera Buffer"),
+            size: std::mem::size_of::<state::CameraUniform>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let draws = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Occlusion Draw Buffer"),
+            size: objects.len() as u64 * geometry::DRAW_COMMAND_SIZE,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::INDIRECT,
+            mapped_at_creation: false,
+        });
+
+        let copy_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Hi-Z Copy Bind Group Layout"),
+            entries: &[
+                texture_entry(0, wgpu::TextureSampleType::Depth, multisampled),
+                storage_texture_entry(1),
+            ],
+        });
+
+        let downsample_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Hi-Z Downsample Bind Group Layout"),
+            entries: &[
+                texture_entry(
+                    0,
+                    wgpu::TextureSampleType::Float { filterable: false },
+                    false,
+                ),
+                storage_texture_entry(1),
+            ],
+        });
+
+        let cull_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Occlusion Cull Bind Group Layout"),
+            entries: &[
+                texture_entry(
+                    0,
+                    wgpu::TextureSampleType::Float { filterable: false },
+                    false,
+                ),
+                buffer_entry(1, wgpu::BufferBindingType::Storage { read_only: true }),
+                buffer_entry(2, wgpu::BufferBindingType::Storage { read_only: false }),
+                buffer_entry(3, wgpu::BufferBindingType::Uniform),
+            ],
+        });
+
+        let pipelines = Self::create_pipelines(
+            device,
+            shaders,
+            multisampled,
+            reversed_z,
+            &copy_layout,
+            &downsample_layout,
+            &cull_layout,
+        )?;
+
+        let (mip_sizes, copy_group, downsample_groups, cull_group) = Self::create_pyramid(
+            device,
+            depth,
+            width,
+            height,
+            &copy_layout,
+            &downsample_layout,
+            &cull_layout,
+            &object_buffer,
+            &draws,
+            &previous_camera,
+        );
+
+        Ok(Self {
+            enabled: true,
+            draws,
+            depth_valid: false,
+            multisampled,
+            reversed_z,
+            object_count: objects.len() as u32,
+            objects: object_buffer,
+            previous_camera,
+            pipelines,
+            copy_layout,
+            downsample_layout,
+            cull_layout,
+            mip_sizes,
+            copy_group,
+            downsample_groups,
+            cull_group,
+        })
+    }
+
+    fn create_pipelines(
+        device: &wgpu::Device,
+        shaders: &mut Preprocessor,
+        multisampled: bool,
+        reversed_z: bool,
+        copy_layout: &wgpu::BindGroupLayout,
+        downsample_layout: &wgpu::BindGroupLayout,
+        cull_layout: &wgpu::BindGroupLayout,
+    ) -> Result<Pipelines> {
+        let mut create = |defines: Defines,
+                          entry_point: &str,
+                          layout: &wgpu::BindGroupLayout|
+         -> Result<wgpu::ComputePipeline> {
+            let defines = if reversed_z {
+                defines.with("REVERSED_Z")
+            } else {
+                defines
+            };
+            let module = shaders.module(device, "occlusion.wgsl", &defines, &[entry_point])?;
+            let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some(entry_point),
+                bind_group_layouts: &[layout],
+                push_constant_ranges: &[],
+            });
+            Ok(
+                device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                    label: Some(entry_point),
+                    layout: Some(&layout),
+                    module: &module,
+                    entry_point,
+                }),
+            )
+        };
+
+        let copy_defines = if multisampled {
+            Defines::new(&["COPY_DEPTH", "MULTISAMPLED"])
+        } else {
+            Defines::new(&["COPY_DEPTH"])
+        };
+
+        Ok(Pipelines {
+            copy: create(copy_defines, "copy_depth", copy_layout)?,
+            downsample: create(
+                Defines::new(&["DOWNSAMPLE"]),
+                "downsample",
+                downsample_layout,
+            )?,
+            cull: create(Defines::new(&["CULL"]), "cull", cull_layout)?,
+        })
+    }
+
+    #[allow(clippy::too_many_arguments, clippy::type_complexity)]
+    fn create_pyramid(
+        device: &wgpu::Device,
+        depth: &texture::Texture,
+        width: u32,
+        height: u32,
+        copy_layout: &wgpu::BindGroupLayout,
+        downsample_layout: &wgpu::BindGroupLayout,
+        cull_layout: &wgpu::BindGroupLayout,
+        objects: &wgpu::Buffer,
+        draws: &wgpu::Buffer,
+        camera_buffer: &wgpu::Buffer,
+    ) -> (
+        Vec<(u32, u32)>,
+        wgpu::BindGroup,
+        Vec<wgpu::BindGroup>,
+        wgpu::BindGroup,
+    ) {
+        let mut mip_sizes = vec![(width, height)];
+        while let Some(&(w, h)) = mip_sizes.last() {
+            if w == 1 && h == 1 {
+                break;
+            }
+            mip_sizes.push(((w / 2).max(1), (h / 2).max(1)));
+        }
+
+        let hiz = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Hi-Z Pyramid"),
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: mip_sizes.len() as u32,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: HIZ_FORMAT,
+            usage: wgpu::TextureUsages::STORAGE_BINDING | wgpu::TextureUsages::TEXTURE_BINDING,
+        });
+
+        let mip_views: Vec<wgpu::TextureView> = (0..mip_sizes.len() as u32)
+            .map(|level| {
+                hiz.create_view(&wgpu::TextureViewDescriptor {
+                    base_mip_level: level,
+                    mip_level_count: NonZeroU32::new(1),
+                    ..Default::default()
+                })
+            })
+            .collect();
+
+        let copy_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Hi-Z Copy Bind Group"),
+            layout: copy_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&depth.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&mip_views[0]),
+                },
+            ],
+        });
+
+        let downsample_groups = mip_views
+            .windows(2)
+            .map(|pair| {
+                device.create_bind_group(&wgpu::BindGroupDescriptor {
+                    label: Some("Hi-Z Downsample Bind Group"),
+                    layout: downsample_layout,
+                    entries: &[
+                        wgpu::BindGroupEntry {
+                            binding: 0,
+                            resource: wgpu::BindingResource::TextureView(&pair[0]),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 1,
+                            resource: wgpu::BindingResource::TextureView(&pair[1]),
+                        },
+                    ],
+                })
+            })
+            .collect();
+
+        let hiz_view = hiz.create_view(&wgpu::TextureViewDescriptor::default());
+        let cull_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Occlusion Cull Bind Group"),
+            layout: cull_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&hiz_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: objects.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: draws.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: camera_buffer.as_entire_binding(),
+                },
+            ],
+        });
+
+        (mip_sizes, copy_group, downsample_groups, cull_group)
+    }
+
+    /// Has to be called with the new depth texture whenever it's recreated.
+    pub fn resize(
+        &mut self,
+        device: &wgpu::Device,
+        depth: &texture::Texture,
+        width: u32,
+        height: u32,
+    ) {
+        let (mip_sizes, copy_group, downsample_groups, cull_group) = Self::create_pyramid(
+            device,
+            depth,
+            width,
+            height,
+            &self.copy_layout,
+            &self.downsample_layout,
+            &self.cull_layout,
+            &self.objects,
+            &self.draws,
+            &self.previous_camera,
+        );
+        self.mip_sizes = mip_sizes;
+        self.copy_group = copy_group;
+        self.downsample_groups = downsample_groups;
+        self.cull_group = cull_group;
+        self.depth_valid = false;
+    }
+
+    /// Rebuilds the compute pipelines, keeping the old ones on errors.
+    pub fn reload(&mut self, device: &wgpu::Device, shaders: &mut Preprocessor) {
+        match Self::create_pipelines(
+            device,
+            shaders,
+            self.multisampled,
+            self.reversed_z,
+            &self.copy_layout,
+            &self.downsample_layout,
+            &self.cull_layout,
+        ) {
+            Ok(pipelines) => self.pipelines = pipelines,
+            Err(e) => log::error!("{:?}", e),
+        }
+    }
+
+    /// Switches the pyramid to the other depth convention. The depth of the
+    /// frame before the switch can't be used. Unlike `reload` nothing changes
+    /// on errors, old pipelines would compare depths the wrong way around.
+    pub fn set_reversed_z(
+        &mut self,
+        device: &wgpu::Device,
+        shaders: &mut Preprocessor,
+        reversed_z: bool,
+    ) -> Result<()> {
+        self.pipelines = Self::create_pipelines(
+            device,
+            shaders,
+            self.multisampled,
+            reversed_z,
+            &self.copy_layout,
+            &self.downsample_layout,
+            &self.cull_layout,
+        )?;
+        self.reversed_z = reversed_z;
+        self.depth_valid = false;
+        Ok(())
+    }
+
+    /// The camera last frame's depth was rendered with, which is what the
+    /// pyramid has to be tested with. Call before the camera uniform is
+    /// updated for the new frame.
+    pub fn set_previous_camera(&self, queue: &wgpu::Queue, camera: &state::CameraUniform) {
+        queue.write_buffer(&self.previous_camera, 0, bytemuck::cast_slice(&[*camera]));
+    }
+
+    /// Whether `draws` is filled in this frame.
+    pub fn active(&self) -> bool {
+        self.enabled && self.depth_valid
+    }
+
+    /// Fills `draws` from the pyramid the last frame built. Has to run
+    /// before the scene is drawn.
+    pub fn cull(&self, encoder: &mut wgpu::CommandEncoder) {
+        if !self.active() {
+            return;
+        }
+
+        let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("Occlusion Culling"),
+        });
+        pass.set_pipeline(&self.pipelines.cull);
+        pass.set_bind_group(0, &self.cull_group, &[]);
+        pass.dispatch(workgroups(self.object_count, 64), 1, 1);
+    }
+
+    /// Builds the pyramid for the next frame out of the depth buffer. Has to
+    /// run after the opaque scene and before anything translucent writes
+    /// depth, or what's behind it would be culled.
+    pub fn build_pyramid(&self, encoder: &mut wgpu::CommandEncoder) {
+        if !self.enabled {
+            return;
+        }
+
+        let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("Hi-Z Pyramid"),
+        });
+
+        let (width, height) = self.mip_sizes[0];
+        pass.set_pipeline(&self.pipelines.copy);
+        pass.set_bind_group(0, &self.copy_group, &[]);
+        pass.dispatch(workgroups(width, 8), workgroups(height, 8), 1);
+
+        pass.set_pipeline(&self.pipelines.downsample);
+        for (group, &(width, height)) in self.downsample_groups.iter().zip(&self.mip_sizes[1..]) {
+            pass.set_bind_group(0, group, &[]);
+            pass.dispatch(workgroups(width, 8), workgroups(height, 8), 1);
+        }
+    }
+}
@@ -0,0 +1,209 @@
+#include "camera.wgsl"
+
+// Hierarchical depth: mip 0 is last frame's depth buffer, every level after
+// that keeps the farthest depth of the texels below it. A box is hidden if
+// its nearest point is behind the farthest depth of the area it covers.
+// With REVERSED_Z far is 0 instead of 1.
+
+fn farther(a: f32, b: f32) -> f32 {
+#ifdef REVERSED_Z
+    return min(a, b);
+#else
+    return max(a, b);
+#endif
+}
+
+#ifdef COPY_DEPTH
+#ifdef MULTISAMPLED
+[[group(0), binding(0)]]
+var depth: texture_depth_multisampled_2d;
+#else
+[[group(0), binding(0)]]
+var depth: texture_depth_2d;
+#endif
+
+[[group(0), binding(1)]]
+var hiz_out: texture_storage_2d<r32float, write>;
+
+[[stage(compute), workgroup_size(8, 8)]]
+fn copy_depth([[builtin(global_invocation_id)]] id: vec3<u32>) {
+    let size = textureDimensions(hiz_out);
+    let coords = vec2<i32>(id.xy);
+    if (coords.x >= size.x || coords.y >= size.y) {
+        return;
+    }
+
+    // Sample 0 is good enough for culling
+    let value = textureLoad(depth, coords, 0);
+    textureStore(hiz_out, coords, vec4<f32>(value, 0.0, 0.0, 0.0));
+}
+#endif
+
+#ifdef DOWNSAMPLE
+[[group(0), binding(0)]]
+var hiz_in: texture_2d<f32>;
+
+[[group(0), binding(1)]]
+var hiz_out: texture_storage_2d<r32float, write>;
+
+fn load_clamped(coords: vec2<i32>, size: vec2<i32>) -> f32 {
+    return textureLoad(hiz_in, min(coords, size - vec2<i32>(1, 1)), 0).x;
+}
+
+[[stage(compute), workgroup_size(8, 8)]]
+fn downsample([[builtin(global_invocation_id)]] id: vec3<u32>) {
+    let size = textureDimensions(hiz_out);
+    let coords = vec2<i32>(id.xy);
+    if (coords.x >= size.x || coords.y >= size.y) {
+        return;
+    }
+
+    let in_size = textureDimensions(hiz_in);
+    let base = coords * 2;
+    var value = farther(
+        farther(load_clamped(base, in_size), load_clamped(base + vec2<i32>(1, 0), in_size)),
+        farther(load_clamped(base + vec2<i32>(0, 1), in_size), load_clamped(base + vec2<i32>(1, 1), in_size)),
+    );
+
+    // Odd sizes leave a row or column that the last texel has to cover too
+    let odd_x = in_size.x % 2 == 1 && coords.x == size.x - 1;
+    let odd_y = in_size.y % 2 == 1 && coords.y == size.y - 1;
+    if (odd_x) {
+        value = farther(value, farther(load_clamped(base + vec2<i32>(2, 0), in_size), load_clamped(base + vec2<i32>(2, 1), in_size)));
+    }
+    if (odd_y) {
+        value = farther(value, farther(load_clamped(base + vec2<i32>(0, 2), in_size), load_clamped(base + vec2<i32>(1, 2), in_size)));
+    }
+    if (odd_x && odd_y) {
+        value = farther(value, load_clamped(base + vec2<i32>(2, 2), in_size));
+    }
+
+    textureStore(hiz_out, coords, vec4<f32>(value, 0.0, 0.0, 0.0));
+}
+#endif
+
+#ifdef CULL
+struct Object {
+    min: vec4<f32>;
+    max: vec4<f32>;
+    first_index: u32;
+    index_count: u32;
+    pad0: u32;
+    pad1: u32;
+};
+
+[[block]]
+struct Objects {
+    data: [[stride(48)]] array<Object>;
+};
+
+// Same layout as wgpu's DrawIndexedIndirect
+struct DrawIndexed {
+    index_count: u32;
+    instance_count: u32;
+    first_index: u32;
+    base_vertex: i32;
+    first_instance: u32;
+};
+
+[[block]]
+struct Draws {
+    data: [[stride(20)]] array<DrawIndexed>;
+};
+
+[[group(0), binding(0)]]
+var hiz: texture_2d<f32>;
+
+[[group(0), binding(1)]]
+var<storage, read> objects: Objects;
+
+[[group(0), binding(2)]]
+var<storage, read_write> draws: Draws;
+
+// The camera of the frame the pyramid comes from, not this one's
+[[group(0), binding(3)]]
+var<uniform> previous_camera: CameraUniform;
+
+fn is_visible(object: Object) -> bool {
+    var ndc_min = vec3<f32>(1.0, 1.0, 1.0);
+    var ndc_max = vec3<f32>(-1.0, -1.0, 0.0);
+    for (var i: u32 = 0u; i < 8u; i = i + 1u) {
+        let corner = vec3<f32>(
+            select(object.min.x, object.max.x, (i & 1u) != 0u),
+            select(object.min.y, object.max.y, (i & 2u) != 0u),
+            select(object.min.z, object.max.z, (i & 4u) != 0u),
+        );
+        let clip = previous_camera.view_proj * vec4<f32>(corner, 1.0);
+
+        // Crosses the near plane, too close to say anything
+        if (clip.w <= 0.0) {
+            return true;
+        }
+
+        let ndc = clip.xyz / clip.w;
+        ndc_min = min(ndc_min, ndc);
+        ndc_max = max(ndc_max, ndc);
+    }
+
+    // Partly off last frame's screen, the pyramid knows nothing about it and
+    // it may have just turned into view
+    if (any(ndc_min.xy < vec2<f32>(-1.0, -1.0)) || any(ndc_max.xy > vec2<f32>(1.0, 1.0))) {
+        return true;
+    }
+
+#ifdef REVERSED_Z
+    let nearest = ndc_max.z;
+    if (nearest >= 1.0) {
+        return true;
+    }
+#else
+    let nearest = ndc_min.z;
+    if (nearest <= 0.0) {
+        return true;
+    }
+#endif
+
+    // Covered pixels, y points down in texture space
+    let size = vec2<f32>(textureDimensions(hiz));
+    let low = clamp(vec2<f32>(ndc_min.x, -ndc_max.y) * 0.5 + vec2<f32>(0.5, 0.5), vec2<f32>(0.0, 0.0), vec2<f32>(1.0, 1.0)) * size;
+    let high = clamp(vec2<f32>(ndc_max.x, -ndc_min.y) * 0.5 + vec2<f32>(0.5, 0.5), vec2<f32>(0.0, 0.0), vec2<f32>(1.0, 1.0)) * size;
+
+    // The level where the rectangle is at most 2x2 texels
+    let extent = max(high.x - low.x, high.y - low.y);
+    let levels = textureNumLevels(hiz);
+    let level = min(i32(ceil(log2(max(extent, 1.0)))), levels - 1);
+
+    let level_size = textureDimensions(hiz, level);
+    let last = level_size - vec2<i32>(1, 1);
+    let a = min(vec2<i32>(low) >> vec2<u32>(u32(level), u32(level)), last);
+    let b = min(vec2<i32>(high) >> vec2<u32>(u32(level), u32(level)), last);
+
+    let farthest = farther(
+        farther(textureLoad(hiz, a, level).x, textureLoad(hiz, vec2<i32>(b.x, a.y), level).x),
+        farther(textureLoad(hiz, vec2<i32>(a.x, b.y), level).x, textureLoad(hiz, b, level).x),
+    );
+
+#ifdef REVERSED_Z
+    return nearest >= farthest;
+#else
+    return nearest <= farthest;
+#endif
+}
+
+[[stage(compute), workgroup_size(64)]]
+fn cull([[builtin(global_invocation_id)]] id: vec3<u32>) {
+    let index = id.x;
+    if (index >= arrayLength(&objects.data)) {
+        return;
+    }
+
+    let object = objects.data[index];
+    var draw: DrawIndexed;
+    draw.index_count = select(0u, object.index_count, is_visible(object));
+    draw.instance_count = 1u;
+    draw.first_index = object.first_index;
+    draw.base_vertex = 0;
+    draw.first_instance = 0u;
+    draws.data[index] = draw;
+}
+#endif
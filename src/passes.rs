@@ -0,0 +1,456 @@
+use crate::mesh;
+use crate::postprocess;
+use crate::render_graph::{Context, Extent, Node, RenderGraph, ResourceId, TextureDesc};
+use crate::state;
+use crate::texture;
+
+/// Draws the scene into `color`, resolving into `resolve` when multisampled.
+pub struct ScenePass {
+    pub color: ResourceId,
+    pub resolve: Option<ResourceId>,
+    pub depth: ResourceId,
+}
+
+impl Node for ScenePass {
+    fn execute(&self, ctx: &mut Context, state: &state::State) {
+        let resolve_target = self.resolve.map(|id| ctx.view(id));
+
+        let mut render_pass = ctx.encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Scene Render Pass"),
+            color_attachments: &[wgpu::RenderPassColorAttachment {
+                view: ctx.view(self.color),
+                resolve_target,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(state.clear_color),
+                    store: true,
+                },
+            }],
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view: ctx.view(self.depth),
+                depth_ops: Some(wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(state.far_depth()),
+                    store: true,
+                }),
+                stencil_ops: None,
+            }),
+        });
+
+        render_pass.set_pipeline(state.pipelines.get(state.scene_pipeline));
+
+        // Uniforms
+        render_pass.set_bind_group(1, &state.camera_bind_group, &[]);
+
+        // The fallback wireframe shader pulls vertices itself
+        let indexed = !state.debug_view.barycentric(state.line_polygon_mode);
+        if indexed {
+            state.mesh.bind(&mut render_pass);
+            state.geometry.bind(&mut render_pass);
+        } else {
+            render_pass.set_bind_group(0, &state.geometry.storage, &[]);
+        }
+
+        if state.occlusion.active() {
+            for run in mesh::runs(&state.visible) {
+                state.geometry.draw_indirect(
+                    &mut render_pass,
+                    &state.occlusion.draws,
+                    run,
+                    indexed,
+                );
+            }
+        } else {
+            state.geometry.draw_indirect(
+                &mut render_pass,
+                &state.draw_list.buffer,
+                state.draw_list.commands(),
+                indexed,
+            );
+        }
+
+        if indexed {
+            render_pass.set_pipeline(state.pipelines.get(state.cutout_pipeline));
+            state.objects.draw_cutout(&mut render_pass);
+
+            render_pass.set_pipeline(state.pipelines.get(state.grass_pipeline));
+            state.grass.draw(
+                &mut render_pass,
+                &state.mesh,
+                &state.visible,
+                state.render_camera.pos,
+            );
+        }
+
+        if state.atmosphere.sky {
+            render_pass.set_pipeline(state.pipelines.get(state.sky_pipeline));
+            render_pass.set_bind_group(0, &state.camera_bind_group, &[]);
+            render_pass.draw(0..3, 0..1);
+        }
+    }
+}
+
+/// The terrain seen by the camera mirrored below the water, see
+/// `Camera::reflected`.
+pub struct ReflectionPass {
+    pub color: ResourceId,
+    pub depth: ResourceId,
+}
+
+impl Node for ReflectionPass {
+    fn execute(&self, ctx: &mut Context, state: &state::State) {
+        let mut render_pass = ctx.encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Reflection Render Pass"),
+            color_attachments: &[wgpu::RenderPassColorAttachment {
+                view: ctx.view(self.color),
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(state.clear_color),
+                    store: true,
+                },
+            }],
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view: ctx.view(self.depth),
+                depth_ops: Some(wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(state.far_depth()),
+                    store: true,
+                }),
+                stencil_ops: None,
+            }),
+        });
+
+        render_pass.set_pipeline(state.pipelines.get(state.reflection_pipeline));
+        render_pass.set_bind_group(1, &state.reflection_camera_bind_group, &[]);
+        state.mesh.bind(&mut render_pass);
+        state.geometry.bind(&mut render_pass);
+        render_pass.draw_indexed(state.mesh.indices.clone(), 0, 0..1);
+
+        if state.atmosphere.sky {
+            render_pass.set_pipeline(state.pipelines.get(state.reflection_sky_pipeline));
+            render_pass.set_bind_group(0, &state.reflection_camera_bind_group, &[]);
+            render_pass.draw(0..3, 0..1);
+        }
+    }
+}
+
+/// Copies `input` into `output`, both transients of the same size and format.
+pub struct CopyPass {
+    pub input: ResourceId,
+    pub output: ResourceId,
+}
+
+impl Node for CopyPass {
+    fn execute(&self, ctx: &mut Context, _state: &state::State) {
+        ctx.encoder.copy_texture_to_texture(
+            ctx.texture(self.input).as_image_copy(),
+            ctx.texture(self.output).as_image_copy(),
+            wgpu::Extent3d {
+                width: ctx.size.0,
+                height: ctx.size.1,
+                depth_or_array_layers: 1,
+            },
+        );
+    }
+}
+
+/// The water plane, refracting `scene` (a copy of the opaque scene) and
+/// reflecting `reflection`.
+pub struct WaterPass {
+    pub scene: ResourceId,
+    pub reflection: ResourceId,
+    pub color: ResourceId,
+    pub resolve: Option<ResourceId>,
+    pub depth: ResourceId,
+}
+
+impl Node for WaterPass {
+    fn execute(&self, ctx: &mut Context, state: &state::State) {
+        let bind_group =
+            state
+                .water
+                .bind_group(ctx.device, ctx.view(self.scene), ctx.view(self.reflection));
+        let resolve_target = self.resolve.map(|id| ctx.view(id));
+
+        let mut render_pass = ctx.encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Water Render Pass"),
+            color_attachments: &[wgpu::RenderPassColorAttachment {
+                view: ctx.view(self.color),
+                resolve_target,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: true,
+                },
+            }],
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view: ctx.view(self.depth),
+                depth_ops: Some(wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: true,
+                }),
+                stencil_ops: None,
+            }),
+        });
+
+        render_pass.set_pipeline(state.pipelines.get(state.water_pipeline));
+        render_pass.set_bind_group(0, &bind_group, &[]);
+        render_pass.set_bind_group(1, &state.camera_bind_group, &[]);
+        render_pass.draw(0..6, 0..1);
+    }
+}
+
+/// Alpha blended objects over the opaque scene, back to front.
+pub struct TransparentPass {
+    pub color: ResourceId,
+    pub resolve: Option<ResourceId>,
+    pub depth: ResourceId,
+}
+
+impl Node for TransparentPass {
+    fn execute(&self, ctx: &mut Context, state: &state::State) {
+        let resolve_target = self.resolve.map(|id| ctx.view(id));
+
+        let mut render_pass = ctx.encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Transparent Render Pass"),
+            color_attachments: &[wgpu::RenderPassColorAttachment {
+                view: ctx.view(self.color),
+                resolve_target,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: true,
+                },
+            }],
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view: ctx.view(self.depth),
+                depth_ops: Some(wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: true,
+                }),
+                stencil_ops: None,
+            }),
+        });
+
+        render_pass.set_pipeline(state.pipelines.get(state.transparent_pipeline));
+        render_pass.set_bind_group(1, &state.camera_bind_group, &[]);
+        state.geometry.bind(&mut render_pass);
+        state.objects.draw_blended(&mut render_pass);
+    }
+}
+
+/// Tests the terrain against last frame's depth pyramid, see
+/// `OcclusionCuller`.
+pub struct OcclusionPass;
+
+impl Node for OcclusionPass {
+    fn execute(&self, ctx: &mut Context, state: &state::State) {
+        state.occlusion.cull(ctx.encoder);
+    }
+}
+
+/// Builds the next frame's depth pyramid out of the opaque scene's depth.
+/// The culler binds the depth texture itself, it's only declared for
+/// ordering.
+pub struct HiZPass;
+
+impl Node for HiZPass {
+    fn execute(&self, ctx: &mut Context, state: &state::State) {
+        state.occlusion.build_pyramid(ctx.encoder);
+    }
+}
+
+/// Draws the lines added to `state.debug` this frame on top of the scene,
+/// depth tested against it.
+pub struct DebugLinePass {
+    pub color: ResourceId,
+    pub resolve: Option<ResourceId>,
+    pub depth: ResourceId,
+}
+
+impl Node for DebugLinePass {
+    fn execute(&self, ctx: &mut Context, state: &state::State) {
+        let resolve_target = self.resolve.map(|id| ctx.view(id));
+
+        let mut render_pass = ctx.encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Debug Line Render Pass"),
+            color_attachments: &[wgpu::RenderPassColorAttachment {
+                view: ctx.view(self.color),
+                resolve_target,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: true,
+                },
+            }],
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view: ctx.view(self.depth),
+                depth_ops: Some(wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: true,
+                }),
+                stencil_ops: None,
+            }),
+        });
+
+        render_pass.set_pipeline(state.pipelines.get(state.debug_lines_pipeline));
+        render_pass.set_bind_group(0, &state.camera_bind_group, &[]);
+        state.debug.draw(&mut render_pass);
+    }
+}
+
+/// Runs `state.post_process.effects[index]`.
+pub struct EffectPass {
+    pub index: usize,
+    pub input: ResourceId,
+    pub output: ResourceId,
+}
+
+impl Node for EffectPass {
+    fn execute(&self, ctx: &mut Context, state: &state::State) {
+        state.post_process.effects[self.index].apply(
+            ctx.device,
+            ctx.queue,
+            ctx.encoder,
+            ctx.view(self.input),
+            ctx.view(self.output),
+            ctx.size,
+        );
+    }
+}
+
+pub struct BlitPass {
+    pub input: ResourceId,
+    pub output: ResourceId,
+}
+
+impl Node for BlitPass {
+    fn execute(&self, ctx: &mut Context, state: &state::State) {
+        state.post_process.blit(
+            ctx.device,
+            ctx.queue,
+            ctx.encoder,
+            ctx.view(self.input),
+            ctx.view(self.output),
+            ctx.size,
+        );
+    }
+}
+
+/// The frame: occlusion culling, reflection, scene, depth pyramid, water, transparent
+/// objects, debug lines, every post processing effect and the blit
+/// to the surface. Expects "depth" and "backbuffer" to be imported on execute.
+pub fn build_render_graph(
+    device: &wgpu::Device,
+    surface_cfg: &wgpu::SurfaceConfiguration,
+    sample_count: u32,
+    post_process: &postprocess::PostProcess,
+) -> RenderGraph {
+    let mut graph = RenderGraph::new(surface_cfg.width, surface_cfg.height);
+
+    let depth = graph.import("depth");
+    let backbuffer = graph.import("backbuffer");
+
+    let hdr_desc = TextureDesc {
+        format: postprocess::HDR_FORMAT,
+        extent: Extent::Surface,
+        sample_count: 1,
+    };
+    let hdr = graph.create_texture("hdr", hdr_desc);
+
+    // Multisampled scenes are drawn into msaa_color and resolved into hdr
+    let (color, resolve, writes) = if sample_count > 1 {
+        let msaa = graph.create_texture(
+            "msaa_color",
+            TextureDesc {
+                sample_count,
+                ..hdr_desc
+            },
+        );
+        (msaa, Some(hdr), vec![msaa, hdr, depth])
+    } else {
+        (hdr, None, vec![hdr, depth])
+    };
+
+    // Doesn't touch the depth, reading it only makes the scene come after
+    graph.add_pass("occlusion", &[depth], &[], OcclusionPass);
+
+    // Half resolution is plenty for something that gets distorted by waves
+    let reflection_desc = TextureDesc {
+        extent: Extent::Scaled(0.5),
+        ..hdr_desc
+    };
+    let reflection = graph.create_texture("reflection", reflection_desc);
+    let reflection_depth = graph.create_texture(
+        "reflection_depth",
+        TextureDesc {
+            format: texture::Texture::DEPTH_FORMAT,
+            ..reflection_desc
+        },
+    );
+    let reflection_pass = ReflectionPass {
+        color: reflection,
+        depth: reflection_depth,
+    };
+    graph.add_pass(
+        "reflection",
+        &[],
+        &[reflection, reflection_depth],
+        reflection_pass,
+    );
+
+    let scene = ScenePass {
+        color,
+        resolve,
+        depth,
+    };
+    graph.add_pass("scene", &[], &writes, scene);
+
+    // Before the water writes depth, so the terrain under it isn't culled
+    graph.add_pass("hi-z", &[depth], &[], HiZPass);
+
+    let scene_copy = graph.create_texture("scene_copy", hdr_desc);
+    let copy = CopyPass {
+        input: hdr,
+        output: scene_copy,
+    };
+    graph.add_pass("scene copy", &[hdr], &[scene_copy], copy);
+
+    let water = WaterPass {
+        scene: scene_copy,
+        reflection,
+        color,
+        resolve,
+        depth,
+    };
+    graph.add_pass("water", &[scene_copy, reflection], &writes, water);
+
+    let transparent = TransparentPass {
+        color,
+        resolve,
+        depth,
+    };
+    graph.add_pass("transparent", &[], &writes, transparent);
+
+    let debug_lines = DebugLinePass {
+        color,
+        resolve,
+        depth,
+    };
+    graph.add_pass("debug lines", &[], &writes, debug_lines);
+
+    let mut current = hdr;
+    for (index, effect) in post_process.effects.iter().enumerate() {
+        let output = graph.create_texture(effect.name(), hdr_desc);
+        let pass = EffectPass {
+            index,
+            input: current,
+            output,
+        };
+        graph.add_pass(effect.name(), &[current], &[output], pass);
+        current = output;
+    }
+
+    let blit = BlitPass {
+        input: current,
+        output: backbuffer,
+    };
+    graph.add_pass("blit", &[current], &[backbuffer], blit);
+
+    graph.compile(device);
+    graph
+}
@@ -0,0 +1,328 @@
+use anyhow::*;
+use std::collections::HashMap;
+
+use crate::preprocessor;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct PipelineId(usize);
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct DepthState {
+    pub format: wgpu::TextureFormat,
+    pub write: bool,
+    pub compare: wgpu::CompareFunction,
+    pub bias: i32,
+    // f32::to_bits so the whole thing stays hashable
+    pub slope_scale_bits: u32,
+}
+
+/// Everything that makes a render pipeline different from another. Doubles
+/// as the key of `Cache`, so asking twice for the same thing is cheap.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct PipelineBuilder {
+    pub label: &'static str,
+    pub layout: &'static str,
+    pub shader: &'static str,
+    pub defines: preprocessor::Defines,
+    pub vertex_entry: &'static str,
+    pub fragment_entry: &'static str,
+    pub vertex_layouts: Vec<wgpu::VertexBufferLayout<'static>>,
+    pub color_format: wgpu::TextureFormat,
+    pub blend: Option<wgpu::BlendState>,
+    pub write_mask: wgpu::ColorWrites,
+    pub depth: Option<DepthState>,
+    pub topology: wgpu::PrimitiveTopology,
+    pub front_face: wgpu::FrontFace,
+    pub cull_mode: Option<wgpu::Face>,
+    pub polygon_mode: wgpu::PolygonMode,
+    pub sample_count: u32,
+    pub alpha_to_coverage: bool,
+}
+
+impl PipelineBuilder {
+    /// Opaque triangles with back face culling, `main` as both entry points
+    /// and no depth buffer. `layout` is a name given to `Cache::add_layout`.
+    pub fn new(
+        label: &'static str,
+        layout: &'static str,
+        shader: &'static str,
+        color_format: wgpu::TextureFormat,
+    ) -> Self {
+        Self {
+            label,
+            layout,
+            shader,
+            defines: preprocessor::Defines::default(),
+            vertex_entry: "main",
+            fragment_entry: "main",
+            vertex_layouts: Vec::new(),
+            color_format,
+            blend: Some(wgpu::BlendState::REPLACE),
+            write_mask: wgpu::ColorWrites::all(),
+            depth: None,
+            topology: wgpu::PrimitiveTopology::TriangleList,
+            front_face: wgpu::FrontFace::Ccw,
+            cull_mode: Some(wgpu::Face::Back),
+            polygon_mode: wgpu::PolygonMode::Fill,
+            sample_count: 1,
+            alpha_to_coverage: false,
+        }
+    }
+
+    pub fn defines(mut self, defines: preprocessor::Defines) -> Self {
+        self.defines = defines;
+        self
+    }
+
+    pub fn vertex_layout(mut self, layout: wgpu::VertexBufferLayout<'static>) -> Self {
+        self.vertex_layouts.push(layout);
+        self
+    }
+
+    pub fn blend(mut self, blend: Option<wgpu::BlendState>) -> Self {
+        self.blend = blend;
+        self
+    }
+
+    pub fn depth(
+        mut self,
+        format: wgpu::TextureFormat,
+        write: bool,
+        compare: wgpu::CompareFunction,
+    ) -> Self {
+        self.depth = Some(DepthState {
+            format,
+            write,
+            compare,
+            bias: 0,
+            slope_scale_bits: 0.0f32.to_bits(),
+        });
+        self
+    }
+
+    pub fn topology(mut self, topology: wgpu::PrimitiveTopology) -> Self {
+        self.topology = topology;
+        self
+    }
+
+    pub fn cull_mode(mut self, cull_mode: Option<wgpu::Face>) -> Self {
+        self.cull_mode = cull_mode;
+        self
+    }
+
+    pub fn polygon_mode(mut self, polygon_mode: wgpu::PolygonMode) -> Self {
+        self.polygon_mode = polygon_mode;
+        self
+    }
+
+    pub fn multisample(mut self, sample_count: u32, alpha_to_coverage: bool) -> Self {
+        self.sample_count = sample_count;
+        self.alpha_to_coverage = alpha_to_coverage;
+        self
+    }
+
+    /// The same pipeline for a depth buffer cleared to 0 with near things
+    /// closer to 1.
+    fn reversed_z(&self) -> Self {
+        let mut builder = self.clone();
+        builder.defines = builder.defines.with("REVERSED_Z");
+        if let Some(depth) = builder.depth.as_mut() {
+            depth.compare = reverse_compare(depth.compare);
+            depth.bias = -depth.bias;
+            depth.slope_scale_bits = (-f32::from_bits(depth.slope_scale_bits)).to_bits();
+        }
+        builder
+    }
+
+    fn create(
+        &self,
+        device: &wgpu::Device,
+        layout: &wgpu::PipelineLayout,
+        shader: &wgpu::ShaderModule,
+    ) -> wgpu::RenderPipeline {
+        device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some(self.label),
+            layout: Some(layout),
+            vertex: wgpu::VertexState {
+                module: shader,
+                entry_point: self.vertex_entry,
+                buffers: &self.vertex_layouts,
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: shader,
+                entry_point: self.fragment_entry,
+                targets: &[wgpu::ColorTargetState {
+                    format: self.color_format,
+                    blend: self.blend,
+                    write_mask: self.write_mask,
+                }],
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: self.topology,
+                strip_index_format: None,
+                front_face: self.front_face,
+                cull_mode: self.cull_mode,
+                polygon_mode: self.polygon_mode,
+                clamp_depth: false,
+                conservative: false,
+            },
+            depth_stencil: self.depth.map(|depth| wgpu::DepthStencilState {
+                format: depth.format,
+                depth_write_enabled: depth.write,
+                depth_compare: depth.compare,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState {
+                    constant: depth.bias,
+                    slope_scale: f32::from_bits(depth.slope_scale_bits),
+                    clamp: 0.0,
+                },
+            }),
+            multisample: wgpu::MultisampleState {
+                count: self.sample_count,
+                mask: !0,
+                alpha_to_coverage_enabled: self.alpha_to_coverage,
+            },
+        })
+    }
+}
+
+/// `compare` for depth going the other way, `Less` becomes `Greater`.
+pub fn reverse_compare(compare: wgpu::CompareFunction) -> wgpu::CompareFunction {
+    match compare {
+        wgpu::CompareFunction::Less => wgpu::CompareFunction::Greater,
+        wgpu::CompareFunction::LessEqual => wgpu::CompareFunction::GreaterEqual,
+        wgpu::CompareFunction::Greater => wgpu::CompareFunction::Less,
+        wgpu::CompareFunction::GreaterEqual => wgpu::CompareFunction::LessEqual,
+        other => other,
+    }
+}
+
+/// Owns every render pipeline built from a `PipelineBuilder`, handing out
+/// ids that stay valid across shader reloads.
+pub struct Cache {
+    layouts: HashMap<&'static str, wgpu::PipelineLayout>,
+    pipelines: Vec<(PipelineBuilder, wgpu::RenderPipeline)>,
+    ids: HashMap<PipelineBuilder, PipelineId>,
+    /// Flips every depth test and bias and builds the shaders with
+    /// `REVERSED_Z`, for projections where depth goes from 1 near to 0 far.
+    reversed_z: bool,
+}
+
+impl Cache {
+    pub fn new(reversed_z: bool) -> Self {
+        Self {
+            layouts: HashMap::new(),
+            pipelines: Vec::new(),
+            ids: HashMap::new(),
+            reversed_z,
+        }
+    }
+
+    /// Switches the depth convention of every pipeline, ids stay valid. All
+    /// or nothing: a pipeline left in the other convention would fail every
+    /// depth test, so if any doesn't build nothing changes.
+    pub fn set_reversed_z(
+        &mut self,
+        device: &wgpu::Device,
+        shaders: &mut preprocessor::Preprocessor,
+        reversed_z: bool,
+    ) -> Result<()> {
+        if self.reversed_z == reversed_z {
+            return Ok(());
+        }
+
+        let pipelines = self
+            .pipelines
+            .iter()
+            .map(|(builder, _)| {
+                self.build(device, shaders, builder, reversed_z)
+                    .with_context(|| format!("building pipeline {}", builder.label))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        for ((_, old), new) in self.pipelines.iter_mut().zip(pipelines) {
+            *old = new;
+        }
+        self.reversed_z = reversed_z;
+        Ok(())
+    }
+
+    pub fn add_layout(
+        &mut self,
+        device: &wgpu::Device,
+        name: &'static str,
+        bind_group_layouts: &[&wgpu::BindGroupLayout],
+    ) {
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some(name),
+            bind_group_layouts,
+            push_constant_ranges: &[],
+        });
+        self.layouts.insert(name, layout);
+    }
+
+    fn build(
+        &self,
+        device: &wgpu::Device,
+        shaders: &mut preprocessor::Preprocessor,
+        builder: &PipelineBuilder,
+        reversed_z: bool,
+    ) -> Result<wgpu::RenderPipeline> {
+        let layout = self
+            .layouts
+            .get(builder.layout)
+            .ok_or_else(|| anyhow!("unknown pipeline layout {}", builder.layout))?;
+
+        let reversed;
+        let builder = if reversed_z {
+            reversed = builder.reversed_z();
+            &reversed
+        } else {
+            builder
+        };
+
+        let shader = shaders.module(
+            device,
+            builder.shader,
+            &builder.defines,
+            &[builder.vertex_entry, builder.fragment_entry],
+        )?;
+
+        Ok(builder.create(device, layout, &shader))
+    }
+
+    /// Returns the pipeline matching `builder`, building it the first time.
+    pub fn request(
+        &mut self,
+        device: &wgpu::Device,
+        shaders: &mut preprocessor::Preprocessor,
+        builder: &PipelineBuilder,
+    ) -> Result<PipelineId> {
+        if let Some(&id) = self.ids.get(builder) {
+            return Ok(id);
+        }
+
+        let pipeline = self
+            .build(device, shaders, builder, self.reversed_z)
+            .with_context(|| format!("building pipeline {}", builder.label))?;
+        let id = PipelineId(self.pipelines.len());
+        self.pipelines.push((builder.clone(), pipeline));
+        self.ids.insert(builder.clone(), id);
+        Ok(id)
+    }
+
+    pub fn get(&self, id: PipelineId) -> &wgpu::RenderPipeline {
+        &self.pipelines[id.0].1
+    }
+
+    /// Rebuilds everything after the shaders changed. Pipelines whose shader
+    /// doesn't compile anymore keep their previous version, which is only
+    /// right because the depth convention stays the same.
+    pub fn reload(&mut self, device: &wgpu::Device, shaders: &mut preprocessor::Preprocessor) {
+        for i in 0..self.pipelines.len() {
+            match self.build(device, shaders, &self.pipelines[i].0, self.reversed_z) {
+                Ok(pipeline) => self.pipelines[i].1 = pipeline,
+                Err(e) => log::error!("{}: {:?}", self.pipelines[i].0.label, e),
+            }
+        }
+    }
+}
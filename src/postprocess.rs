@@ -0,0 +1,641 @@
+use std::any::Any;
+use wgpu::util::DeviceExt;
+
+use crate::texture;
+
+/// Format of the intermediate targets. The scene is rendered here and every
+/// effect reads and writes it, only the final blit goes to the surface format.
+pub const HDR_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+
+/// Entry points postprocess.wgsl has to provide.
+pub const ENTRY_POINTS: &[&str] = &[
+    "main",
+    "blit",
+    "tone_map",
+    "bright",
+    "blur",
+    "bloom_composite",
+    "fxaa",
+    "vignette",
+];
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct Params {
+    texel_size: [f32; 4],
+    values: [f32; 4],
+}
+
+/// One fullscreen triangle draw with a fragment entry point of postprocess.wgsl.
+pub struct FullscreenPass {
+    pipeline: wgpu::RenderPipeline,
+    entry_point: String,
+    format: wgpu::TextureFormat,
+    layout: wgpu::BindGroupLayout,
+    sampler: wgpu::Sampler,
+    params: wgpu::Buffer,
+}
+
+impl FullscreenPass {
+    pub fn new(
+        device: &wgpu::Device,
+        shader: &wgpu::ShaderModule,
+        entry_point: &str,
+        format: wgpu::TextureFormat,
+    ) -> Self {
+        let texture_entry = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                multisampled: false,
+                sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                view_dimension: wgpu::TextureViewDimension::D2,
+            },
+            count: None,
+        };
+
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Post Process Bind Group Layout"),
+            entries: &[
+                texture_entry(0),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler {
+                        comparison: false,
+                        filtering: true,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                        ty: wgpu::BufferBindingType::Uniform,
+                    },
+                    count: None,
+                },
+                texture_entry(3),
+            ],
+        });
+
+        let pipeline = Self::create_pipeline(device, &layout, shader, entry_point, format);
+
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            ..Default::default()
+        });
+
+        let params = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Post Process Params"),
+            contents: bytemuck::cast_slice(&[Params {
+                texel_size: [0.0; 4],
+                values: [0.0; 4],
+            }]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        Self {
+            pipeline,
+            entry_point: entry_point.to_string(),
+            format,
+            layout,
+            sampler,
+            params,
+        }
+    }
+
+    fn create_pipeline(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        shader: &wgpu::ShaderModule,
+        entry_point: &str,
+        format: wgpu::TextureFormat,
+    ) -> wgpu::RenderPipeline {
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Post Process Pipeline Layout"),
+            bind_group_layouts: &[layout],
+            push_constant_ranges: &[],
+        });
+
+        device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some(entry_point),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: shader,
+                entry_point: "main",
+                buffers: &[],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: shader,
+                entry_point,
+                targets: &[wgpu::ColorTargetState {
+                    format,
+                    blend: Some(wgpu::BlendState::REPLACE),
+                    write_mask: wgpu::ColorWrites::all(),
+                }],
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+        })
+    }
+
+    /// Recreates the pipeline with a new version of the shader.
+    pub fn reload(&mut self, device: &wgpu::Device, shader: &wgpu::ShaderModule) {
+        self.pipeline =
+            Self::create_pipeline(device, &self.layout, shader, &self.entry_point, self.format);
+    }
+
+    /// Draws `input` (and optionally `extra`) into `output`. `size` is the size
+    /// of `input`, `values` ends up in `params.values` in the shader.
+    #[allow(clippy::too_many_arguments)]
+    pub fn run(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        input: &wgpu::TextureView,
+        extra: Option<&wgpu::TextureView>,
+        output: &wgpu::TextureView,
+        size: (u32, u32),
+        values: [f32; 4],
+    ) {
+        queue.write_buffer(
+            &self.params,
+            0,
+            bytemuck::cast_slice(&[Params {
+                texel_size: [1.0 / size.0 as f32, 1.0 / size.1 as f32, 0.0, 0.0],
+                values,
+            }]),
+        );
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &self.layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(input),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&self.sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: self.params.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: wgpu::BindingResource::TextureView(extra.unwrap_or(input)),
+                },
+            ],
+            label: Some("Post Process Bind Group"),
+        });
+
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Post Process Pass"),
+            color_attachments: &[wgpu::RenderPassColorAttachment {
+                view: output,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                    store: true,
+                },
+            }],
+            depth_stencil_attachment: None,
+        });
+
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, &bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+}
+
+/// A step of the post processing chain. Reads the previous step's output and
+/// writes to `output`, both in `HDR_FORMAT` and of the size given to `resize`.
+pub trait Effect: Any {
+    fn name(&self) -> &str;
+
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+
+    fn resize(&mut self, _device: &wgpu::Device, _width: u32, _height: u32) {}
+
+    /// Called with the new module when postprocess.wgsl is hot reloaded.
+    fn reload(&mut self, device: &wgpu::Device, shader: &wgpu::ShaderModule);
+
+    fn apply(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        input: &wgpu::TextureView,
+        output: &wgpu::TextureView,
+        size: (u32, u32),
+    );
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ToneMapOperator {
+    Reinhard,
+    Aces,
+}
+
+pub struct ToneMap {
+    pub operator: ToneMapOperator,
+    pub exposure: f32,
+    /// Use 1.0 when presenting to an sRGB surface, the hardware encodes it already.
+    pub gamma: f32,
+    pass: FullscreenPass,
+}
+
+impl ToneMap {
+    pub fn new(device: &wgpu::Device, shader: &wgpu::ShaderModule, gamma: f32) -> Self {
+        Self {
+            operator: ToneMapOperator::Aces,
+            exposure: 1.0,
+            gamma,
+            pass: FullscreenPass::new(device, shader, "tone_map", HDR_FORMAT),
+        }
+    }
+}
+
+impl Effect for ToneMap {
+    fn name(&self) -> &str {
+        "tone_map"
+    }
+
+    fn reload(&mut self, device: &wgpu::Device, shader: &wgpu::ShaderModule) {
+        self.pass.reload(device, shader);
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+
+    fn apply(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        input: &wgpu::TextureView,
+        output: &wgpu::TextureView,
+        size: (u32, u32),
+    ) {
+        let operator = match self.operator {
+            ToneMapOperator::Reinhard => 0.0,
+            ToneMapOperator::Aces => 1.0,
+        };
+        let values = [self.exposure, 1.0 / self.gamma, operator, 0.0];
+        self.pass
+            .run(device, queue, encoder, input, None, output, size, values);
+    }
+}
+
+/// Bright pass at half resolution, separable gaussian blur and an additive
+/// composite on top of the input.
+pub struct Bloom {
+    pub threshold: f32,
+    pub knee: f32,
+    pub intensity: f32,
+    bright: FullscreenPass,
+    blur_horizontal: FullscreenPass,
+    blur_vertical: FullscreenPass,
+    composite: FullscreenPass,
+    ping: texture::Texture,
+    pong: texture::Texture,
+    half_size: (u32, u32),
+}
+
+impl Bloom {
+    pub fn new(
+        device: &wgpu::Device,
+        shader: &wgpu::ShaderModule,
+        width: u32,
+        height: u32,
+    ) -> Self {
+        let half_size = Self::half_size(width, height);
+        let (ping, pong) = Self::create_targets(device, half_size);
+        Self {
+            threshold: 1.0,
+            knee: 0.5,
+            intensity: 0.6,
+            bright: FullscreenPass::new(device, shader, "bright", HDR_FORMAT),
+            blur_horizontal: FullscreenPass::new(device, shader, "blur", HDR_FORMAT),
+            blur_vertical: FullscreenPass::new(device, shader, "blur", HDR_FORMAT),
+            composite: FullscreenPass::new(device, shader, "bloom_composite", HDR_FORMAT),
+            ping,
+            pong,
+            half_size,
+        }
+    }
+
+    fn half_size(width: u32, height: u32) -> (u32, u32) {
+        ((width / 2).max(1), (height / 2).max(1))
+    }
+
+    fn create_targets(
+        device: &wgpu::Device,
+        (width, height): (u32, u32),
+    ) -> (texture::Texture, texture::Texture) {
+        (
+            texture::Texture::create_render_target(
+                device,
+                width,
+                height,
+                HDR_FORMAT,
+                1,
+                "bloom_ping",
+            ),
+            texture::Texture::create_render_target(
+                device,
+                width,
+                height,
+                HDR_FORMAT,
+                1,
+                "bloom_pong",
+            ),
+        )
+    }
+}
+
+impl Effect for Bloom {
+    fn name(&self) -> &str {
+        "bloom"
+    }
+
+    fn reload(&mut self, device: &wgpu::Device, shader: &wgpu::ShaderModule) {
+        self.bright.reload(device, shader);
+        self.blur_horizontal.reload(device, shader);
+        self.blur_vertical.reload(device, shader);
+        self.composite.reload(device, shader);
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+
+    fn resize(&mut self, device: &wgpu::Device, width: u32, height: u32) {
+        self.half_size = Self::half_size(width, height);
+        let (ping, pong) = Self::create_targets(device, self.half_size);
+        self.ping = ping;
+        self.pong = pong;
+    }
+
+    fn apply(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        input: &wgpu::TextureView,
+        output: &wgpu::TextureView,
+        size: (u32, u32),
+    ) {
+        let bright = [self.threshold, self.knee, 0.0, 0.0];
+        self.bright.run(
+            device,
+            queue,
+            encoder,
+            input,
+            None,
+            &self.ping.view,
+            size,
+            bright,
+        );
+
+        let horizontal = [1.0, 0.0, 0.0, 0.0];
+        self.blur_horizontal.run(
+            device,
+            queue,
+            encoder,
+            &self.ping.view,
+            None,
+            &self.pong.view,
+            self.half_size,
+            horizontal,
+        );
+
+        let vertical = [0.0, 1.0, 0.0, 0.0];
+        self.blur_vertical.run(
+            device,
+            queue,
+            encoder,
+            &self.pong.view,
+            None,
+            &self.ping.view,
+            self.half_size,
+            vertical,
+        );
+
+        let composite = [self.intensity, 0.0, 0.0, 0.0];
+        self.composite.run(
+            device,
+            queue,
+            encoder,
+            input,
+            Some(&self.ping.view),
+            output,
+            size,
+            composite,
+        );
+    }
+}
+
+pub struct Fxaa {
+    pub edge_threshold: f32,
+    pub edge_threshold_min: f32,
+    pub subpixel: f32,
+    pass: FullscreenPass,
+}
+
+impl Fxaa {
+    pub fn new(device: &wgpu::Device, shader: &wgpu::ShaderModule) -> Self {
+        Self {
+            edge_threshold: 0.125,
+            edge_threshold_min: 0.0312,
+            subpixel: 0.75,
+            pass: FullscreenPass::new(device, shader, "fxaa", HDR_FORMAT),
+        }
+    }
+}
+
+impl Effect for Fxaa {
+    fn name(&self) -> &str {
+        "fxaa"
+    }
+
+    fn reload(&mut self, device: &wgpu::Device, shader: &wgpu::ShaderModule) {
+        self.pass.reload(device, shader);
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+
+    fn apply(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        input: &wgpu::TextureView,
+        output: &wgpu::TextureView,
+        size: (u32, u32),
+    ) {
+        let values = [
+            self.edge_threshold,
+            self.edge_threshold_min,
+            self.subpixel,
+            0.0,
+        ];
+        self.pass
+            .run(device, queue, encoder, input, None, output, size, values);
+    }
+}
+
+pub struct Vignette {
+    pub intensity: f32,
+    pub radius: f32,
+    pub smoothness: f32,
+    pass: FullscreenPass,
+}
+
+impl Vignette {
+    pub fn new(device: &wgpu::Device, shader: &wgpu::ShaderModule) -> Self {
+        Self {
+            intensity: 0.4,
+            radius: 0.75,
+            smoothness: 0.45,
+            pass: FullscreenPass::new(device, shader, "vignette", HDR_FORMAT),
+        }
+    }
+}
+
+impl Effect for Vignette {
+    fn name(&self) -> &str {
+        "vignette"
+    }
+
+    fn reload(&mut self, device: &wgpu::Device, shader: &wgpu::ShaderModule) {
+        self.pass.reload(device, shader);
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+
+    fn apply(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        input: &wgpu::TextureView,
+        output: &wgpu::TextureView,
+        size: (u32, u32),
+    ) {
+        let values = [self.intensity, self.radius, self.smoothness, 0.0];
+        self.pass
+            .run(device, queue, encoder, input, None, output, size, values);
+    }
+}
+
+/// The ordered list of effects. Each one becomes a pass of the render graph
+/// reading the previous one's output, see `passes::build_render_graph`.
+pub struct PostProcess {
+    pub shader: wgpu::ShaderModule,
+    pub effects: Vec<Box<dyn Effect>>,
+    blit: FullscreenPass,
+}
+
+impl PostProcess {
+    pub fn new(
+        device: &wgpu::Device,
+        surface_cfg: &wgpu::SurfaceConfiguration,
+        shader: wgpu::ShaderModule,
+    ) -> Self {
+        let blit = FullscreenPass::new(device, &shader, "blit", surface_cfg.format);
+
+        // sRGB surfaces do the gamma encoding for us
+        let gamma = if surface_cfg.format.describe().srgb {
+            1.0
+        } else {
+            2.2
+        };
+
+        let effects: Vec<Box<dyn Effect>> = vec![
+            Box::new(Bloom::new(
+                device,
+                &shader,
+                surface_cfg.width,
+                surface_cfg.height,
+            )),
+            Box::new(ToneMap::new(device, &shader, gamma)),
+            Box::new(Fxaa::new(device, &shader)),
+            Box::new(Vignette::new(device, &shader)),
+        ];
+
+        Self {
+            shader,
+            effects,
+            blit,
+        }
+    }
+
+    /// Rebuilds every pipeline with a new version of postprocess.wgsl.
+    pub fn reload(&mut self, device: &wgpu::Device, shader: wgpu::ShaderModule) {
+        self.shader = shader;
+        self.blit.reload(device, &self.shader);
+        for effect in self.effects.iter_mut() {
+            effect.reload(device, &self.shader);
+        }
+    }
+
+    pub fn effect_mut<T: Effect>(&mut self) -> Option<&mut T> {
+        self.effects
+            .iter_mut()
+            .find_map(|effect| effect.as_any_mut().downcast_mut::<T>())
+    }
+
+    /// Moves the effect called `name` to `index` in the chain.
+    pub fn reorder(&mut self, name: &str, index: usize) {
+        if let Some(current) = self.effects.iter().position(|effect| effect.name() == name) {
+            let effect = self.effects.remove(current);
+            let index = index.min(self.effects.len());
+            self.effects.insert(index, effect);
+        }
+    }
+
+    pub fn remove(&mut self, name: &str) -> Option<Box<dyn Effect>> {
+        let index = self
+            .effects
+            .iter()
+            .position(|effect| effect.name() == name)?;
+        Some(self.effects.remove(index))
+    }
+
+    pub fn resize(&mut self, device: &wgpu::Device, width: u32, height: u32) {
+        for effect in self.effects.iter_mut() {
+            effect.resize(device, width, height);
+        }
+    }
+
+    /// Copies the end of the chain to `output`, which is in the surface format.
+    pub fn blit(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        input: &wgpu::TextureView,
+        output: &wgpu::TextureView,
+        size: (u32, u32),
+    ) {
+        self.blit
+            .run(device, queue, encoder, input, None, output, size, [0.0; 4]);
+    }
+}
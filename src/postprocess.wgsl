@@ -0,0 +1,162 @@
+struct VertexOutput {
+    [[builtin(position)]] clip_position: vec4<f32>;
+    [[location(0)]] uv: vec2<f32>;
+};
+
+[[block]]
+struct Params {
+    // xy: size of one input texel in uv space
+    texel_size: vec4<f32>;
+    // Meaning depends on the entry point, see postprocess.rs
+    values: vec4<f32>;
+};
+
+[[group(0), binding(0)]]
+var t_input: texture_2d<f32>;
+
+[[group(0), binding(1)]]
+var s_input: sampler;
+
+[[group(0), binding(2)]]
+var<uniform> params: Params;
+
+[[group(0), binding(3)]]
+var t_extra: texture_2d<f32>;
+
+// Fullscreen triangle, no vertex buffer needed.
+[[stage(vertex)]]
+fn main([[builtin(vertex_index)]] index: u32) -> VertexOutput {
+    let xy = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    var out: VertexOutput;
+    out.clip_position = vec4<f32>(xy * 2.0 - vec2<f32>(1.0, 1.0), 0.0, 1.0);
+    out.uv = vec2<f32>(xy.x, 1.0 - xy.y);
+    return out;
+}
+
+fn luminance(color: vec3<f32>) -> f32 {
+    return dot(color, vec3<f32>(0.2126, 0.7152, 0.0722));
+}
+
+// naga doesn't know about smoothstep yet
+fn smooth_step(edge0: f32, edge1: f32, x: f32) -> f32 {
+    let t = clamp((x - edge0) / (edge1 - edge0), 0.0, 1.0);
+    return t * t * (3.0 - 2.0 * t);
+}
+
+[[stage(fragment)]]
+fn blit(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    return textureSample(t_input, s_input, in.uv);
+}
+
+// values: x = exposure, y = 1 / gamma, z = operator (0 Reinhard, 1 ACES)
+[[stage(fragment)]]
+fn tone_map(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    let hdr = textureSample(t_input, s_input, in.uv);
+    let color = hdr.rgb * params.values.x;
+
+    var mapped: vec3<f32>;
+    if (params.values.z < 0.5) {
+        mapped = color / (color + vec3<f32>(1.0, 1.0, 1.0));
+    } else {
+        // Narkowicz's ACES filmic curve fit
+        let a = color * (color * 2.51 + vec3<f32>(0.03, 0.03, 0.03));
+        let b = color * (color * 2.43 + vec3<f32>(0.59, 0.59, 0.59)) + vec3<f32>(0.14, 0.14, 0.14);
+        mapped = clamp(a / b, vec3<f32>(0.0, 0.0, 0.0), vec3<f32>(1.0, 1.0, 1.0));
+    }
+
+    let gamma = vec3<f32>(params.values.y, params.values.y, params.values.y);
+    return vec4<f32>(pow(mapped, gamma), hdr.a);
+}
+
+// values: x = threshold, y = soft knee
+[[stage(fragment)]]
+fn bright(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    let color = textureSample(t_input, s_input, in.uv).rgb;
+    let brightness = max(color.r, max(color.g, color.b));
+    let knee = params.values.x * params.values.y;
+    let curve = clamp(brightness - params.values.x + knee, 0.0, 2.0 * knee);
+    let soft = curve * curve / (4.0 * knee + 0.00001);
+    let contribution = max(soft, brightness - params.values.x) / max(brightness, 0.00001);
+    return vec4<f32>(color * contribution, 1.0);
+}
+
+// values: xy = blur direction
+[[stage(fragment)]]
+fn blur(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    let step = params.values.xy * params.texel_size.xy;
+
+    // 9 tap gaussian folded into 5 linear samples
+    var color = textureSample(t_input, s_input, in.uv).rgb * 0.2270270270;
+    color = color + textureSample(t_input, s_input, in.uv + step * 1.3846153846).rgb * 0.3162162162;
+    color = color + textureSample(t_input, s_input, in.uv - step * 1.3846153846).rgb * 0.3162162162;
+    color = color + textureSample(t_input, s_input, in.uv + step * 3.2307692308).rgb * 0.0702702703;
+    color = color + textureSample(t_input, s_input, in.uv - step * 3.2307692308).rgb * 0.0702702703;
+    return vec4<f32>(color, 1.0);
+}
+
+// values: x = bloom intensity
+[[stage(fragment)]]
+fn bloom_composite(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    let scene = textureSample(t_input, s_input, in.uv);
+    let bloom = textureSample(t_extra, s_input, in.uv).rgb;
+    return vec4<f32>(scene.rgb + bloom * params.values.x, scene.a);
+}
+
+// values: x = edge threshold, y = minimum edge threshold, z = subpixel blending
+[[stage(fragment)]]
+fn fxaa(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    let texel = params.texel_size.xy;
+    let center = textureSample(t_input, s_input, in.uv);
+
+    let luma_m = luminance(center.rgb);
+    let luma_n = luminance(textureSample(t_input, s_input, in.uv + vec2<f32>(0.0, -texel.y)).rgb);
+    let luma_s = luminance(textureSample(t_input, s_input, in.uv + vec2<f32>(0.0, texel.y)).rgb);
+    let luma_e = luminance(textureSample(t_input, s_input, in.uv + vec2<f32>(texel.x, 0.0)).rgb);
+    let luma_w = luminance(textureSample(t_input, s_input, in.uv + vec2<f32>(-texel.x, 0.0)).rgb);
+
+    let luma_max = max(luma_m, max(max(luma_n, luma_s), max(luma_e, luma_w)));
+    let luma_min = min(luma_m, min(min(luma_n, luma_s), min(luma_e, luma_w)));
+    let contrast = luma_max - luma_min;
+
+    let luma_ne = luminance(textureSample(t_input, s_input, in.uv + vec2<f32>(texel.x, -texel.y)).rgb);
+    let luma_nw = luminance(textureSample(t_input, s_input, in.uv + vec2<f32>(-texel.x, -texel.y)).rgb);
+    let luma_se = luminance(textureSample(t_input, s_input, in.uv + vec2<f32>(texel.x, texel.y)).rgb);
+    let luma_sw = luminance(textureSample(t_input, s_input, in.uv + vec2<f32>(-texel.x, texel.y)).rgb);
+
+    // Subpixel blend factor from the local average
+    let average = (2.0 * (luma_n + luma_s + luma_e + luma_w) + luma_ne + luma_nw + luma_se + luma_sw) / 12.0;
+    let subpixel = smooth_step(0.0, 1.0, clamp(abs(average - luma_m) / contrast, 0.0, 1.0));
+    let blend = subpixel * subpixel * params.values.z;
+
+    // Blend perpendicular to the dominant edge direction
+    let horizontal = abs(luma_n + luma_s - 2.0 * luma_m) * 2.0
+        + abs(luma_ne + luma_se - 2.0 * luma_e)
+        + abs(luma_nw + luma_sw - 2.0 * luma_w);
+    let vertical = abs(luma_e + luma_w - 2.0 * luma_m) * 2.0
+        + abs(luma_ne + luma_nw - 2.0 * luma_n)
+        + abs(luma_se + luma_sw - 2.0 * luma_s);
+
+    var offset: vec2<f32>;
+    if (horizontal >= vertical) {
+        offset = vec2<f32>(0.0, select(texel.y, -texel.y, luma_n - luma_m > luma_s - luma_m));
+    } else {
+        offset = vec2<f32>(select(texel.x, -texel.x, luma_w - luma_m > luma_e - luma_m), 0.0);
+    }
+
+    // Sampling has to happen in uniform control flow, so bail out only afterwards
+    let neighbour = textureSample(t_input, s_input, in.uv + offset);
+    if (contrast < max(params.values.y, luma_max * params.values.x)) {
+        return center;
+    }
+    return mix(center, neighbour, vec4<f32>(blend, blend, blend, blend));
+}
+
+// values: x = intensity, y = radius, z = smoothness
+[[stage(fragment)]]
+fn vignette(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    let color = textureSample(t_input, s_input, in.uv);
+    let distance = length(in.uv - vec2<f32>(0.5, 0.5));
+    let falloff = smooth_step(params.values.y, params.values.y - params.values.z, distance);
+    let factor = mix(1.0 - params.values.x, 1.0, falloff);
+    return vec4<f32>(color.rgb * factor, color.a);
+}
@@ -0,0 +1,198 @@
+use anyhow::*;
+use std::collections::{BTreeSet, HashMap, HashSet};
+
+use crate::hot_reload;
+
+/// The set of `#define`s a shader is processed with. Two equal sets always
+/// produce the same source, so this is what permutations are cached by.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Defines(BTreeSet<String>);
+
+impl Defines {
+    pub fn new(names: &[&str]) -> Self {
+        Self(names.iter().map(|name| name.to_string()).collect())
+    }
+
+    pub fn with(mut self, name: &str) -> Self {
+        self.0.insert(name.to_string());
+        self
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.0.contains(name)
+    }
+}
+
+/// Every combination of `features`, from none of them to all of them.
+pub fn permutations(features: &[&str]) -> Vec<Defines> {
+    (0..1u32 << features.len())
+        .map(|mask| {
+            Defines(
+                features
+                    .iter()
+                    .enumerate()
+                    .filter(|(i, _)| mask & (1 << i) != 0)
+                    .map(|(_, name)| name.to_string())
+                    .collect(),
+            )
+        })
+        .collect()
+}
+
+// One level of #ifdef nesting
+struct Branch {
+    parent_active: bool,
+    taken: bool,
+    in_else: bool,
+}
+
+/// Turns the WGSL files it knows about into plain WGSL, handling
+/// `#include "file.wgsl"`, `#define NAME`, `#ifdef NAME`, `#ifndef NAME`,
+/// `#else` and `#endif`. Files are only ever included once per shader.
+pub struct Preprocessor {
+    files: HashMap<String, hot_reload::ShaderFile>,
+    cache: HashMap<(String, Defines), String>,
+}
+
+impl Preprocessor {
+    pub fn new() -> Self {
+        Self {
+            files: HashMap::new(),
+            cache: HashMap::new(),
+        }
+    }
+
+    /// Makes the file available to `process` and `#include` by its file name.
+    pub fn add(&mut self, file: hot_reload::ShaderFile) {
+        self.files.insert(file.name().to_string(), file);
+    }
+
+    /// Polls every file for changes, dropping the cache if anything changed.
+    pub fn poll(&mut self) -> bool {
+        let mut changed = false;
+        for file in self.files.values_mut() {
+            changed |= file.poll();
+        }
+
+        if changed {
+            self.cache.clear();
+        }
+
+        changed
+    }
+
+    pub fn process(&mut self, name: &str, defines: &Defines) -> Result<&str> {
+        let key = (name.to_string(), defines.clone());
+        if !self.cache.contains_key(&key) {
+            let mut defines = defines.0.clone();
+            let mut included = HashSet::new();
+            let mut output = String::new();
+            self.expand(name, &mut defines, &mut included, &mut output)?;
+            self.cache.insert(key.clone(), output);
+        }
+
+        Ok(&self.cache[&key])
+    }
+
+    /// Processes and validates the shader, then makes a module of it.
+    pub fn module(
+        &mut self,
+        device: &wgpu::Device,
+        name: &str,
+        defines: &Defines,
+        entry_points: &[&str],
+    ) -> Result<wgpu::ShaderModule> {
+        let source = self.process(name, defines)?;
+        hot_reload::validate(source, entry_points)
+            .with_context(|| format!("{} with {:?}", name, defines))?;
+
+        Ok(device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+            label: Some(name),
+            source: wgpu::ShaderSource::Wgsl(source.to_string().into()),
+        }))
+    }
+
+    fn expand(
+        &self,
+        name: &str,
+        defines: &mut BTreeSet<String>,
+        included: &mut HashSet<String>,
+        output: &mut String,
+    ) -> Result<()> {
+        if !included.insert(name.to_string()) {
+            return Ok(());
+        }
+
+        let file = self
+            .files
+            .get(name)
+            .ok_or_else(|| anyhow!("unknown shader file {}", name))?;
+
+        let mut branches: Vec<Branch> = Vec::new();
+        let active = |branches: &Vec<Branch>| match branches.last() {
+            Some(branch) => branch.parent_active && (branch.taken != branch.in_else),
+            None => true,
+        };
+
+        for (number, line) in file.source.lines().enumerate() {
+            let location = || format!("{}:{}", name, number + 1);
+            let trimmed = line.trim();
+
+            if !trimmed.starts_with('#') {
+                if active(&branches) {
+                    output.push_str(line);
+                    output.push('\n');
+                }
+                continue;
+            }
+
+            let mut words = trimmed.split_whitespace();
+            let directive = words.next().unwrap_or_default();
+            let argument = words.next();
+
+            match directive {
+                "#ifdef" | "#ifndef" => {
+                    let define = argument.ok_or_else(|| anyhow!("{}: missing name", location()))?;
+                    let defined = defines.contains(define);
+                    branches.push(Branch {
+                        parent_active: active(&branches),
+                        taken: if directive == "#ifdef" {
+                            defined
+                        } else {
+                            !defined
+                        },
+                        in_else: false,
+                    });
+                }
+                "#else" => match branches.last_mut() {
+                    Some(branch) if !branch.in_else => branch.in_else = true,
+                    _ => bail!("{}: #else without #ifdef", location()),
+                },
+                "#endif" => {
+                    branches
+                        .pop()
+                        .ok_or_else(|| anyhow!("{}: #endif without #ifdef", location()))?;
+                }
+                "#define" if active(&branches) => {
+                    let define = argument.ok_or_else(|| anyhow!("{}: missing name", location()))?;
+                    defines.insert(define.to_string());
+                }
+                "#include" if active(&branches) => {
+                    let include = argument
+                        .map(|path| path.trim_matches('"'))
+                        .ok_or_else(|| anyhow!("{}: missing file", location()))?;
+                    self.expand(include, defines, included, output)
+                        .with_context(location)?;
+                }
+                "#define" | "#include" => {}
+                _ => bail!("{}: unknown directive {}", location(), directive),
+            }
+        }
+
+        if !branches.is_empty() {
+            bail!("{}: missing #endif", name);
+        }
+
+        Ok(())
+    }
+}
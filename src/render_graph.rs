@@ -0,0 +1,336 @@
+use std::collections::HashMap;
+
+use crate::state;
+use crate::texture;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct ResourceId(usize);
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Extent {
+    /// Same size as the surface, recreated on resize.
+    Surface,
+    /// Surface size multiplied by a factor, e.g. 0.5 for half resolution.
+    Scaled(f32),
+}
+
+impl Extent {
+    fn resolve(&self, (width, height): (u32, u32)) -> (u32, u32) {
+        match *self {
+            Extent::Surface => (width, height),
+            Extent::Scaled(factor) => (
+                ((width as f32 * factor) as u32).max(1),
+                ((height as f32 * factor) as u32).max(1),
+            ),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct TextureDesc {
+    pub format: wgpu::TextureFormat,
+    pub extent: Extent,
+    pub sample_count: u32,
+}
+
+enum Resource {
+    /// Owned and allocated by the graph, only alive between its first and last use.
+    Transient { name: String, desc: TextureDesc },
+    /// Owned by someone else and handed in to `execute` by name.
+    Imported { name: String },
+}
+
+impl Resource {
+    fn name(&self) -> &str {
+        match self {
+            Resource::Transient { name, .. } | Resource::Imported { name } => name,
+        }
+    }
+}
+
+/// What a pass gets to work with while executing.
+pub struct Context<'a> {
+    pub device: &'a wgpu::Device,
+    pub queue: &'a wgpu::Queue,
+    pub encoder: &'a mut wgpu::CommandEncoder,
+    pub size: (u32, u32),
+    views: Vec<Option<&'a wgpu::TextureView>>,
+    textures: Vec<Option<&'a wgpu::Texture>>,
+}
+
+impl<'a> Context<'a> {
+    pub fn view(&self, id: ResourceId) -> &'a wgpu::TextureView {
+        self.views[id.0].expect("Pass uses a resource it did not declare")
+    }
+
+    /// The texture behind a transient, e.g. for copies. Imports only come
+    /// with views.
+    pub fn texture(&self, id: ResourceId) -> &'a wgpu::Texture {
+        self.textures[id.0].expect("Pass uses a resource it did not declare or an import")
+    }
+}
+
+pub trait Node {
+    fn execute(&self, ctx: &mut Context, state: &state::State);
+}
+
+struct Pass {
+    name: String,
+    reads: Vec<ResourceId>,
+    writes: Vec<ResourceId>,
+    node: Box<dyn Node>,
+}
+
+/// Passes declare which resources they read and write, the graph works out
+/// the order to run them in, drops passes nobody depends on and backs the
+/// transient textures with as few real textures as it can.
+pub struct RenderGraph {
+    resources: Vec<Resource>,
+    passes: Vec<Pass>,
+    order: Vec<usize>,
+    physical: Vec<texture::Texture>,
+    // Index into `physical` for every transient resource
+    aliases: HashMap<ResourceId, usize>,
+    size: (u32, u32),
+}
+
+impl RenderGraph {
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            resources: Vec::new(),
+            passes: Vec::new(),
+            order: Vec::new(),
+            physical: Vec::new(),
+            aliases: HashMap::new(),
+            size: (width, height),
+        }
+    }
+
+    pub fn create_texture(&mut self, name: &str, desc: TextureDesc) -> ResourceId {
+        self.resources.push(Resource::Transient {
+            name: name.to_string(),
+            desc,
+        });
+        ResourceId(self.resources.len() - 1)
+    }
+
+    pub fn import(&mut self, name: &str) -> ResourceId {
+        self.resources.push(Resource::Imported {
+            name: name.to_string(),
+        });
+        ResourceId(self.resources.len() - 1)
+    }
+
+    pub fn add_pass<N: Node + 'static>(
+        &mut self,
+        name: &str,
+        reads: &[ResourceId],
+        writes: &[ResourceId],
+        node: N,
+    ) {
+        self.passes.push(Pass {
+            name: name.to_string(),
+            reads: reads.to_vec(),
+            writes: writes.to_vec(),
+            node: Box::new(node),
+        });
+    }
+
+    /// Orders the passes and allocates the transient textures. Has to be
+    /// called after adding passes and before `execute`.
+    pub fn compile(&mut self, device: &wgpu::Device) {
+        self.order = self.sort();
+        self.allocate(device);
+    }
+
+    pub fn resize(&mut self, device: &wgpu::Device, width: u32, height: u32) {
+        self.size = (width, height);
+        self.allocate(device);
+    }
+
+    fn is_imported(&self, id: ResourceId) -> bool {
+        matches!(self.resources[id.0], Resource::Imported { .. })
+    }
+
+    fn sort(&self) -> Vec<usize> {
+        let count = self.passes.len();
+        let mut dependencies: Vec<Vec<usize>> = vec![Vec::new(); count];
+
+        // Passes are declared in submission order, so a read depends on the
+        // latest write before it and a write on every access before it.
+        let mut last_write: HashMap<ResourceId, usize> = HashMap::new();
+        let mut reads_since_write: HashMap<ResourceId, Vec<usize>> = HashMap::new();
+        for (i, pass) in self.passes.iter().enumerate() {
+            for id in pass.reads.iter() {
+                if let Some(&writer) = last_write.get(id) {
+                    dependencies[i].push(writer);
+                }
+                reads_since_write.entry(*id).or_default().push(i);
+            }
+            for id in pass.writes.iter() {
+                if let Some(&writer) = last_write.get(id) {
+                    dependencies[i].push(writer);
+                }
+                if let Some(readers) = reads_since_write.remove(id) {
+                    dependencies[i].extend(readers.into_iter().filter(|&r| r != i));
+                }
+                last_write.insert(*id, i);
+            }
+        }
+
+        // Only keep passes that (transitively) end up in an imported resource,
+        // everything else is wasted work.
+        let mut alive = vec![false; count];
+        let mut stack: Vec<usize> = (0..count)
+            .filter(|&i| self.passes[i].writes.iter().any(|&id| self.is_imported(id)))
+            .collect();
+        while let Some(i) = stack.pop() {
+            if !alive[i] {
+                alive[i] = true;
+                stack.extend(dependencies[i].iter().copied());
+            }
+        }
+
+        // Kahn's algorithm, picking the earliest declared pass on ties so the
+        // order stays predictable.
+        let mut remaining: Vec<usize> = dependencies
+            .iter()
+            .map(|deps| deps.iter().filter(|&&d| alive[d]).count())
+            .collect();
+        let mut order = Vec::new();
+        let mut done = vec![false; count];
+        while let Some(next) = (0..count).find(|&i| alive[i] && !done[i] && remaining[i] == 0) {
+            done[next] = true;
+            order.push(next);
+            for (i, deps) in dependencies.iter().enumerate() {
+                if !done[i] {
+                    remaining[i] -= deps.iter().filter(|&&d| d == next).count();
+                }
+            }
+        }
+
+        if order.len() != alive.iter().filter(|&&a| a).count() {
+            log::error!("Render graph has a cycle, some passes will not run");
+        }
+
+        order
+    }
+
+    fn allocate(&mut self, device: &wgpu::Device) {
+        // First and last position in `order` each transient is used at
+        let mut lifetimes: Vec<(ResourceId, usize, usize)> = Vec::new();
+        for (position, &i) in self.order.iter().enumerate() {
+            let pass = &self.passes[i];
+            for &id in pass.reads.iter().chain(pass.writes.iter()) {
+                if self.is_imported(id) {
+                    continue;
+                }
+                match lifetimes.iter_mut().find(|(r, _, _)| *r == id) {
+                    Some((_, _, last)) => *last = position,
+                    None => lifetimes.push((id, position, position)),
+                }
+            }
+        }
+
+        // Two transients can share a texture if they look the same and one is
+        // dead before the other is first used.
+        let mut slots: Vec<(TextureDesc, (u32, u32), usize, ResourceId)> = Vec::new();
+        self.aliases.clear();
+        for (id, first, last) in lifetimes {
+            let desc = match &self.resources[id.0] {
+                Resource::Transient { desc, .. } => *desc,
+                Resource::Imported { .. } => unreachable!(),
+            };
+            let size = desc.extent.resolve(self.size);
+            let slot = match slots
+                .iter()
+                .position(|(d, s, free_after, _)| *d == desc && *s == size && *free_after < first)
+            {
+                Some(slot) => {
+                    slots[slot].2 = last;
+                    slot
+                }
+                None => {
+                    slots.push((desc, size, last, id));
+                    slots.len() - 1
+                }
+            };
+            self.aliases.insert(id, slot);
+        }
+
+        self.physical = slots
+            .iter()
+            .map(|(desc, (width, height), _, first_user)| {
+                texture::Texture::create_render_target(
+                    device,
+                    *width,
+                    *height,
+                    desc.format,
+                    desc.sample_count,
+                    self.resources[first_user.0].name(),
+                )
+            })
+            .collect();
+
+        log::debug!(
+            "Render graph: [{}], {} transients in {} textures",
+            self.order
+                .iter()
+                .map(|&i| self.passes[i].name.as_str())
+                .collect::<Vec<_>>()
+                .join(", "),
+            self.aliases.len(),
+            self.physical.len()
+        );
+    }
+
+    /// Records every pass into `encoder`. `imports` provides the views for the
+    /// resources added with `import`, by name.
+    pub fn execute(
+        &self,
+        state: &state::State,
+        encoder: &mut wgpu::CommandEncoder,
+        imports: &[(&str, &wgpu::TextureView)],
+    ) {
+        let views = self
+            .resources
+            .iter()
+            .enumerate()
+            .map(|(i, resource)| match resource {
+                // Transients of culled passes have no texture
+                Resource::Transient { .. } => self
+                    .aliases
+                    .get(&ResourceId(i))
+                    .map(|&slot| &self.physical[slot].view),
+                Resource::Imported { name } => Some(
+                    imports
+                        .iter()
+                        .find(|(import, _)| import == name)
+                        .unwrap_or_else(|| panic!("Missing render graph import {}", name))
+                        .1,
+                ),
+            })
+            .collect();
+
+        let textures = (0..self.resources.len())
+            .map(|i| {
+                self.aliases
+                    .get(&ResourceId(i))
+                    .map(|&slot| &self.physical[slot].texture)
+            })
+            .collect();
+
+        let mut ctx = Context {
+            device: &state.device,
+            queue: &state.queue,
+            encoder,
+            size: self.size,
+            views,
+            textures,
+        };
+
+        for &i in self.order.iter() {
+            self.passes[i].node.execute(&mut ctx, state);
+        }
+    }
+}
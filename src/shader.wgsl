@@ -1,31 +1,58 @@
+#include "camera.wgsl"
+
 struct VertexInput {
     [[location(0)]] position: vec3<f32>;
-    [[location(1)]] normal: vec2<f32>;
+    [[location(1)]] normal: vec3<f32>;
     [[location(2)]] uv: vec2<f32>;
+};
 
+#ifdef INSTANCED
+struct InstanceInput {
+    [[location(3)]] model_0: vec4<f32>;
+    [[location(4)]] model_1: vec4<f32>;
+    [[location(5)]] model_2: vec4<f32>;
+    [[location(6)]] model_3: vec4<f32>;
+    [[location(7)]] color: vec4<f32>;
 };
+#endif
 
 struct VertexOutput {
     [[builtin(position)]] clip_position: vec4<f32>;
     [[location(0)]] uv: vec2<f32>;
-};
-
-[[block]]
-struct CameraUniform {
-    view_pos: vec4<f32>;
-    view_proj: mat4x4<f32>;
+    [[location(1)]] normal: vec3<f32>;
+    [[location(2)]] world_position: vec3<f32>;
+    [[location(3)]] tint: vec4<f32>;
 };
 
 [[group(1), binding(0)]]
 var<uniform> camera: CameraUniform;
 
+[[group(1), binding(1)]]
+var<uniform> atmosphere: AtmosphereUniform;
+
+#include "atmosphere.wgsl"
+
 [[stage(vertex)]]
 fn main(
     model: VertexInput,
+#ifdef INSTANCED
+    instance: InstanceInput,
+#endif
 ) -> VertexOutput {
     var out: VertexOutput;
     out.uv = model.uv;
-    out.clip_position = camera.view_proj * vec4<f32>(model.position, 1.0);
+#ifdef INSTANCED
+    let transform = mat4x4<f32>(instance.model_0, instance.model_1, instance.model_2, instance.model_3);
+    let world_position = transform * vec4<f32>(model.position, 1.0);
+    out.normal = (transform * vec4<f32>(model.normal, 0.0)).xyz;
+    out.tint = instance.color;
+#else
+    let world_position = vec4<f32>(model.position, 1.0);
+    out.normal = model.normal;
+    out.tint = vec4<f32>(1.0, 1.0, 1.0, 1.0);
+#endif
+    out.world_position = world_position.xyz;
+    out.clip_position = camera.view_proj * world_position;
     return out;
 }
 
@@ -35,7 +62,62 @@ var t_diffuse: texture_2d<f32>;
 [[group(0), binding(1)]]
 var s_diffuse: sampler;
 
+#ifdef DEBUG_VIEW
+// One of DEBUG_NORMALS, DEBUG_UVS, DEBUG_DEPTH or DEBUG_OVERDRAW, plain white
+// for wireframes
+fn debug_color(in: VertexOutput) -> vec4<f32> {
+    var color = vec4<f32>(1.0, 1.0, 1.0, 1.0);
+#ifdef DEBUG_NORMALS
+    color = vec4<f32>(normalize(in.normal) * 0.5 + vec3<f32>(0.5, 0.5, 0.5), 1.0);
+#endif
+#ifdef DEBUG_UVS
+    color = vec4<f32>(fract(in.uv), 0.0, 1.0);
+#endif
+#ifdef DEBUG_DEPTH
+    // Distance to the camera squashed into 0..1, near is white
+    let near = exp(-distance(in.world_position, camera.view_pos.xyz) * 0.005);
+    color = vec4<f32>(near, near, near, 1.0);
+#endif
+#ifdef DEBUG_OVERDRAW
+    // Blended additively, every layer adds a bit of heat
+    color = vec4<f32>(0.1, 0.04, 0.01, 1.0);
+#endif
+    return color;
+}
+#endif
+
 [[stage(fragment)]]
 fn main(in: VertexOutput) -> [[location(0)]] vec4<f32> {
-    return textureSample(t_diffuse, s_diffuse, in.uv);
+#ifdef DEBUG_VIEW
+    return debug_color(in);
+#else
+    var color = textureSample(t_diffuse, s_diffuse, in.uv) * in.tint;
+#ifdef ALPHA_CUTOUT
+#ifdef ALPHA_TO_COVERAGE
+    // Derivatives have to be taken before anything is discarded
+    let alpha_width = max(fwidth(color.a), 0.0001);
+#endif
+#endif
+#ifdef CLIP_PLANE
+    if (dot(camera.clip_plane, vec4<f32>(in.world_position, 1.0)) < 0.0) {
+        discard;
+    }
+#endif
+#ifdef ALPHA_CUTOUT
+#ifdef ALPHA_TO_COVERAGE
+    // Sharpen the edge to about a pixel and let coverage antialias it
+    color.a = clamp((color.a - 0.5) / alpha_width + 0.5, 0.0, 1.0);
+#else
+    if (color.a < 0.5) {
+        discard;
+    }
+    color.a = 1.0;
+#endif
+#endif
+#ifdef LIT
+    let diffuse = max(dot(normalize(in.normal), atmosphere.sun.xyz), 0.0);
+    color = vec4<f32>(color.rgb * (0.3 + 0.7 * diffuse), color.a);
+#endif
+    return vec4<f32>(apply_atmosphere(color.rgb, in.world_position, camera.view_pos.xyz), color.a);
+#endif
 }
@@ -0,0 +1,40 @@
+#include "camera.wgsl"
+
+[[group(0), binding(0)]]
+var<uniform> camera: CameraUniform;
+
+[[group(0), binding(1)]]
+var<uniform> atmosphere: AtmosphereUniform;
+
+#include "atmosphere.wgsl"
+
+struct VertexOutput {
+    [[builtin(position)]] clip_position: vec4<f32>;
+    [[location(0)]] ndc: vec2<f32>;
+};
+
+#ifdef REVERSED_Z
+let far_depth: f32 = 0.0;
+#else
+let far_depth: f32 = 1.0;
+#endif
+
+// A fullscreen triangle on the far plane, only seen where nothing was drawn
+[[stage(vertex)]]
+fn main([[builtin(vertex_index)]] vertex_index: u32) -> VertexOutput {
+    let uv = vec2<f32>(f32((vertex_index << 1u) & 2u), f32(vertex_index & 2u));
+    var out: VertexOutput;
+    out.ndc = uv * 2.0 - vec2<f32>(1.0, 1.0);
+    out.clip_position = vec4<f32>(out.ndc, far_depth, 1.0);
+    return out;
+}
+
+[[stage(fragment)]]
+fn main(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    // Between two points along the pixel's ray, both finite even without a
+    // far plane
+    let nearer = camera.inv_view_proj * vec4<f32>(in.ndc, mix(0.75, 0.25, far_depth), 1.0);
+    let farther = camera.inv_view_proj * vec4<f32>(in.ndc, mix(0.25, 0.75, far_depth), 1.0);
+    let direction = normalize(farther.xyz / farther.w - nearer.xyz / nearer.w);
+    return vec4<f32>(sky_color(direction), 1.0);
+}
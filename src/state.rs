@@ -1,11 +1,33 @@
 use anyhow::*;
+use image::GenericImageView;
 use std::time;
-use wgpu::{util::DeviceExt, VertexBufferLayout};
+use wgpu::util::DeviceExt;
 use winit::{event::*, event_loop::ControlFlow, window::Window};
 
+use crate::atmosphere;
 use crate::camera;
+use crate::camera_path;
+use crate::culling;
+use crate::debug_draw;
+use crate::debug_view;
+use crate::ecs;
+use crate::game;
+use crate::gamepad;
+use crate::geometry;
+use crate::grass;
+use crate::input;
 use crate::mesh;
+use crate::meshgen;
+use crate::occlusion;
+use crate::passes;
+use crate::pipeline;
+use crate::postprocess;
+use crate::preprocessor;
+use crate::render_graph;
 use crate::texture;
+use crate::timestep;
+use crate::transparency;
+use crate::water;
 
 const VERTICES_A: &[[f32; 3]] = &[
     [-0.5, 0.5, 0.0],
@@ -30,31 +52,123 @@ const UVS_A: &[[f32; 2]] = &[
 
 const INDICES_A: &[u32] = &[0, 1, 2, 0, 2, 3];
 
+/// Where R saves the recorded camera path and P loads it from.
+const CAMERA_PATH_FILE: &str = "camera_path.txt";
+/// Key bindings, see `input::Bindings`. Written when rebinding.
+const BINDINGS_FILE: &str = "bindings.txt";
+
+/// Shader features of shader.wgsl that can be toggled with `scene_defines`.
+const SCENE_FEATURES: &[&str] = &[
+    "LIT",
+    "INSTANCED",
+    "ALPHA_CUTOUT",
+    "ALPHA_TO_COVERAGE",
+    "CLIP_PLANE",
+    "REVERSED_Z",
+];
+
 pub struct State {
     pub surface: wgpu::Surface,
     pub device: wgpu::Device,
     pub queue: wgpu::Queue,
     pub surface_cfg: wgpu::SurfaceConfiguration,
     pub size: winit::dpi::PhysicalSize<u32>,
+    /// Background when the atmosphere's sky is off.
     pub clear_color: wgpu::Color,
-    pub render_pipeline: wgpu::RenderPipeline,
+    pub atmosphere: atmosphere::Atmosphere,
+    pub sky_pipeline: pipeline::PipelineId,
+    pub reflection_sky_pipeline: pipeline::PipelineId,
+    pub pipelines: pipeline::Cache,
+    pub scene_pipeline: pipeline::PipelineId,
+    pub shaders: preprocessor::Preprocessor,
+    pub scene_defines: preprocessor::Defines,
+    pub debug_view: debug_view::DebugView,
+    /// Whether wireframes can use `PolygonMode::Line`.
+    pub line_polygon_mode: bool,
+    /// Where the simulation put the camera on the last tick.
     pub camera: camera::Camera,
-    pub projection: camera::Projection,
+    /// Where it was the tick before.
+    pub previous_camera: camera::Camera,
+    /// Between the two, what this frame renders from.
+    pub render_camera: camera::Camera,
+    pub projection: Box<dyn camera::Projection>,
+    /// Top down orthographic projection instead of perspective (M).
+    pub map_view: bool,
+    /// Depth going from 1 near to 0 far (Z), see `pipeline::Cache`.
+    pub reversed_z: bool,
     pub camera_controller: camera::Controller,
+    pub input: input::Input,
+    pub gamepads: gamepad::Gamepads,
+    /// Some while recording the camera (R).
+    pub recorder: Option<camera_path::Recorder>,
+    /// Some while playing a path back (P), the controller is ignored.
+    pub player: Option<camera_path::Player>,
     pub camera_uniform: CameraUniform,
     pub camera_buffer: wgpu::Buffer,
     pub camera_bind_group: wgpu::BindGroup,
     pub frame_count: u64,
+    pub geometry: geometry::GeometryPool,
     pub mesh: mesh::Mesh,
+    /// CPU side copy of the heightmap mesh.
+    pub terrain: mesh::Descriptor,
+    pub height_field: meshgen::HeightField,
+    /// Submeshes of `mesh` that survived frustum culling this frame.
+    pub visible: Vec<usize>,
+    pub cull_stats: culling::Stats,
+    /// Draws of the visible submeshes when not occlusion culling.
+    pub draw_list: geometry::DrawList,
+    pub occlusion: occlusion::OcclusionCuller,
+    pub objects: transparency::Objects,
+    /// Game objects, drawn through `objects`.
+    pub world: ecs::World,
+    /// Runs on `world` every tick.
+    pub systems: ecs::Schedule,
+    pub cutout_pipeline: pipeline::PipelineId,
+    pub transparent_pipeline: pipeline::PipelineId,
+    pub water: water::Water,
+    pub grass: grass::Grass,
+    pub grass_pipeline: pipeline::PipelineId,
+    pub water_pipeline: pipeline::PipelineId,
+    /// Draws the terrain for the water's reflection, clipped at its level.
+    pub reflection_pipeline: pipeline::PipelineId,
+    pub reflection_camera_buffer: wgpu::Buffer,
+    pub reflection_camera_bind_group: wgpu::BindGroup,
+    pub debug: debug_draw::DebugDraw,
+    pub debug_lines_pipeline: pipeline::PipelineId,
+    /// Some while gizmos are shown (G), with the view projection matrix the
+    /// camera had when they were turned on.
+    pub gizmos: Option<cgmath::Matrix4<f32>>,
     pub depth_texture: texture::Texture,
+    pub sample_count: u32,
+    pub post_process: postprocess::PostProcess,
+    pub render_graph: render_graph::RenderGraph,
     pub delta_time: time::Duration,
+    /// Runs the camera, recorder and follow target in fixed ticks.
+    pub timestep: timestep::FixedTimestep,
+    previous_target: cgmath::Point3<f32>,
     pub last_frame_time: time::Instant,
     pub start_time: time::Instant,
+    /// Left mouse button, looks around while held with a free cursor.
     pub mouse_pressed: bool,
+    /// What the cursor should do, `apply_cursor` makes the window follow.
+    pub cursor_mode: CursorMode,
+    // What the window was last set to
+    applied_cursor_mode: Option<CursorMode>,
+    // Grabbing isn't supported everywhere, once it failed clicks stay drags
+    cursor_grab_failed: bool,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum CursorMode {
+    /// Visible, dragging with the left mouse button looks around.
+    Free,
+    /// Hidden and grabbed, moving the mouse looks around. Clicking captures
+    /// it, the release-cursor action (Escape) lets go.
+    Captured,
 }
 
 impl State {
-    pub async fn new(window: &Window) -> Self {
+    pub async fn new(window: &Window, sample_count: u32, reversed_z: bool, tick_rate: f64) -> Self {
         let size = window.inner_size();
         let instance = wgpu::Instance::new(wgpu::Backends::PRIMARY);
         let surface = unsafe { instance.create_surface(window) };
@@ -66,10 +180,16 @@ impl State {
             .await
             .expect("Failed to find an appropriate adapter");
 
+        let sample_count = supported_sample_count(sample_count);
+
+        // Nice to have for wireframes and batching, there are fallbacks
+        let features = adapter.features()
+            & (wgpu::Features::NON_FILL_POLYGON_MODE | wgpu::Features::MULTI_DRAW_INDIRECT);
+
         let (device, queue) = adapter
             .request_device(
                 &wgpu::DeviceDescriptor {
-                    features: wgpu::Features::empty(),
+                    features,
                     limits: wgpu::Limits::default(),
                     label: None,
                 },
@@ -78,6 +198,10 @@ impl State {
             .await
             .unwrap();
 
+        let line_polygon_mode = device
+            .features()
+            .contains(wgpu::Features::NON_FILL_POLYGON_MODE);
+
         let surface_cfg = wgpu::SurfaceConfiguration {
             usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
             format: surface.get_preferred_format(&adapter).unwrap(),
@@ -94,23 +218,116 @@ impl State {
             texture::Texture::from_bytes(&device, &queue, diffuse_bytes, Some("Cool texture"))
                 .unwrap();
 
-        let depth_texture =
-            texture::Texture::create_depth_texture(&device, &surface_cfg, "depth_texture");
+        let depth_texture = texture::Texture::create_depth_texture(
+            &device,
+            &surface_cfg,
+            sample_count,
+            reversed_z,
+            "depth_texture",
+        );
+
+        let mut shaders = preprocessor::Preprocessor::new();
+        shaders.add(shader_file!("camera.wgsl"));
+        shaders.add(shader_file!("atmosphere.wgsl"));
+        shaders.add(shader_file!("shader.wgsl"));
+        shaders.add(shader_file!("postprocess.wgsl"));
+        shaders.add(shader_file!("wireframe.wgsl"));
+        shaders.add(shader_file!("debug_lines.wgsl"));
+        shaders.add(shader_file!("occlusion.wgsl"));
+        shaders.add(shader_file!("water.wgsl"));
+        shaders.add(shader_file!("sky.wgsl"));
+        shaders.add(shader_file!("grass.wgsl"));
 
-        //let mesh_descriptor = mesh::Descriptor {
-        //    vertices: VERTICES_A.to_vec(),
-        //    normals: NORMALS_A.to_vec(),
-        //    uvs: UVS_A.to_vec(),
-        //    triangles: INDICES_A.to_vec(),
-        //    texture: diffuse_texture,
-        //};
+        // Catch broken variants early instead of when they're first used
+        if cfg!(debug_assertions) {
+            for defines in preprocessor::permutations(SCENE_FEATURES) {
+                if let Err(e) = shaders.module(&device, "shader.wgsl", &defines, &["main"]) {
+                    log::error!("{:?}", e);
+                }
+            }
+        }
+
+        let post_process_shader = shaders
+            .module(
+                &device,
+                "postprocess.wgsl",
+                &preprocessor::Defines::default(),
+                postprocess::ENTRY_POINTS,
+            )
+            .unwrap();
+        let post_process =
+            postprocess::PostProcess::new(&device, &surface_cfg, post_process_shader);
+        let render_graph =
+            passes::build_render_graph(&device, &surface_cfg, sample_count, &post_process);
+
+        let quad = mesh::Descriptor {
+            vertices: VERTICES_A.to_vec(),
+            normals: NORMALS_A.to_vec(),
+            uvs: UVS_A.to_vec(),
+            triangles: INDICES_A.to_vec(),
+            submeshes: Vec::new(),
+            texture: diffuse_texture,
+        };
 
         let perlin_bytes = include_bytes!("cool.png");
         let perlin_image = image::load_from_memory(perlin_bytes).unwrap();
-        let mut mesh_descriptor =
+        let terrain =
             mesh::Descriptor::from_height_map(&perlin_image, 200, 200, 0.5, &device, &queue);
+        let height_field = meshgen::HeightField::from_terrain(&terrain, 200, 200);
+
+        let mut geometry = geometry::GeometryPool::new(&device);
+        let mesh = terrain.bake(&device, &queue, &mut geometry);
+
+        let (map_width, map_height) = perlin_image.dimensions();
+        // Raw heights, the terrain's texture is decoded as sRGB
+        let water_height_map = texture::Texture::from_image_with_format(
+            &device,
+            &queue,
+            &perlin_image,
+            wgpu::TextureFormat::Rgba8Unorm,
+            Some("Water Height Map"),
+        )
+        .unwrap();
+        let water = water::Water::new(
+            &device,
+            &water_height_map,
+            (map_width as f32 * 0.5, map_height as f32 * 0.5),
+            255.0 * 0.5,
+            &mesh.bounds,
+        );
+
+        let aabb = mesh.bounds.aabb;
+        let grass = grass::Grass::new(
+            &device,
+            &mut shaders,
+            &geometry,
+            &mesh,
+            &grass::density_map(&device, &queue),
+            &transparency::foliage_texture(&device, &queue),
+            grass::Rules {
+                min_height: water.level + 0.5,
+                max_height: aabb.min.y + (aabb.max.y - aabb.min.y) * 0.7,
+                max_slope: cgmath::Deg(35.0),
+                density: 0.6,
+                foliage: 0.04,
+            },
+        )
+        .unwrap();
 
-        let mesh = mesh_descriptor.bake(&device);
+        let foliage = mesh::Descriptor {
+            vertices: VERTICES_A.to_vec(),
+            normals: NORMALS_A.to_vec(),
+            uvs: UVS_A.to_vec(),
+            triangles: INDICES_A.to_vec(),
+            submeshes: Vec::new(),
+            texture: transparency::foliage_texture(&device, &queue),
+        };
+        let mut world = ecs::World::new();
+        game::register(&mut world);
+        let mut objects = transparency::Objects::new(&device);
+        let (glass, leaves) =
+            transparency::demo_meshes(&device, &queue, &mut geometry, &mut objects, quad, foliage);
+        game::spawn_demo_scene(&mut world, glass, leaves, |x, z| height_field.height(x, z));
 
         let clear_color = wgpu::Color {
             r: 0.1,
@@ -118,24 +335,35 @@ impl State {
             b: 0.3,
             a: 1.0,
         };
+        let atmosphere = atmosphere::Atmosphere::new(&device, clear_color);
+        atmosphere.update(&queue);
 
-        let shader = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
-            label: Some("Shader"),
-            source: wgpu::ShaderSource::Wgsl(include_str!("shader.wgsl").into()),
-        });
+        game::spawn_sun(
+            &mut world,
+            atmosphere.sun_direction,
+            atmosphere.sun_intensity,
+        );
+        let systems = game::schedule();
+        log::info!(
+            "Systems: {}",
+            systems.names().collect::<Vec<_>>().join(", ")
+        );
 
         let camera = camera::Camera::new((0.0, 5.0, 10.0), cgmath::Deg(-90.0), cgmath::Deg(-20.0));
-        let projection = camera::Projection::new(
-            surface_cfg.width,
-            surface_cfg.height,
-            cgmath::Deg(90.0),
-            0.1,
-            10000.0,
-        );
-        let camera_controller = camera::Controller::new(10.0, 0.3);
+        let projection =
+            create_projection(false, reversed_z, surface_cfg.width, surface_cfg.height);
+        let camera_controller = camera::Controller::new(10.0, 0.004);
+        let bindings = if std::path::Path::new(BINDINGS_FILE).exists() {
+            input::Bindings::load(BINDINGS_FILE).unwrap_or_else(|e| {
+                log::error!("{:?}", e);
+                input::Bindings::defaults()
+            })
+        } else {
+            input::Bindings::defaults()
+        };
 
         let mut camera_uniform = CameraUniform::new();
-        camera_uniform.update_view_proj(&camera, &projection);
+        camera_uniform.update_view_proj(&camera, projection.as_ref());
 
         let camera_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
             label: Some("Camera Uniform Buffer"),
@@ -143,85 +371,152 @@ impl State {
             usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
         });
 
+        let uniform_entry = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Buffer {
+                has_dynamic_offset: false,
+                min_binding_size: None,
+                ty: wgpu::BufferBindingType::Uniform,
+            },
+            count: None,
+        };
         let camera_bind_group_layout =
             device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
                 label: Some("Camera bind group layout"),
-                entries: &[wgpu::BindGroupLayoutEntry {
-                    binding: 0,
-                    visibility: wgpu::ShaderStages::VERTEX,
-                    ty: wgpu::BindingType::Buffer {
-                        has_dynamic_offset: false,
-                        min_binding_size: None,
-                        ty: wgpu::BufferBindingType::Uniform,
-                    },
-                    count: None,
-                }],
+                entries: &[uniform_entry(0), uniform_entry(1)],
             });
 
         let camera_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
             layout: &camera_bind_group_layout,
-            entries: &[wgpu::BindGroupEntry {
-                binding: 0,
-                resource: camera_buffer.as_entire_binding(),
-            }],
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: camera_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: atmosphere.buffer.as_entire_binding(),
+                },
+            ],
             label: Some("Camera bind group"),
         });
 
-        let render_pipeline_layout =
-            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-                label: Some("Render Pipeline Layout"),
-                bind_group_layouts: &[&mesh.texture_layout, &camera_bind_group_layout],
-                push_constant_ranges: &[],
-            });
-
-        let render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-            label: Some("Render Pipeline"),
-            layout: Some(&render_pipeline_layout),
-            vertex: wgpu::VertexState {
-                module: &shader,
-                entry_point: "main",
-                buffers: &[VertexBufferLayout {
-                    array_stride: std::mem::size_of::<mesh::Vertex>() as u64,
-                    step_mode: wgpu::VertexStepMode::Vertex,
-                    attributes: &wgpu::vertex_attr_array![
-                        0 => Float32x3, // Position
-                        1 => Float32x3, // Normal
-                        2 => Float32x2  // UV
-                    ],
-                }],
-            },
-            fragment: Some(wgpu::FragmentState {
-                module: &shader,
-                entry_point: "main",
-                targets: &[wgpu::ColorTargetState {
-                    format: surface_cfg.format,
-                    blend: Some(wgpu::BlendState::REPLACE),
-                    write_mask: wgpu::ColorWrites::all(),
-                }],
-            }),
-            primitive: wgpu::PrimitiveState {
-                topology: wgpu::PrimitiveTopology::TriangleList,
-                strip_index_format: None,
-                front_face: wgpu::FrontFace::Ccw,
-                cull_mode: Some(wgpu::Face::Back),
-                polygon_mode: wgpu::PolygonMode::Fill,
-                clamp_depth: false,
-                conservative: false,
-            },
-            depth_stencil: Some(wgpu::DepthStencilState {
-                format: texture::Texture::DEPTH_FORMAT,
-                depth_write_enabled: true,
-                depth_compare: wgpu::CompareFunction::Less,
-                stencil: wgpu::StencilState::default(),
-                bias: wgpu::DepthBiasState::default(),
-            }),
-            multisample: wgpu::MultisampleState {
-                count: 1,
-                mask: !0,
-                alpha_to_coverage_enabled: false,
-            },
+        let reflection_camera_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Reflection Camera Uniform Buffer"),
+            size: std::mem::size_of::<CameraUniform>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let reflection_camera_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &camera_bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: reflection_camera_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: atmosphere.buffer.as_entire_binding(),
+                },
+            ],
+            label: Some("Reflection camera bind group"),
         });
 
+        let mut pipelines = pipeline::Cache::new(reversed_z);
+        pipelines.add_layout(
+            &device,
+            "scene",
+            &[&mesh.texture_layout, &camera_bind_group_layout],
+        );
+        pipelines.add_layout(
+            &device,
+            "wireframe",
+            &[&geometry.storage_layout, &camera_bind_group_layout],
+        );
+        pipelines.add_layout(&device, "debug_lines", &[&camera_bind_group_layout]);
+        pipelines.add_layout(&device, "sky", &[&camera_bind_group_layout]);
+        pipelines.add_layout(
+            &device,
+            "grass",
+            &[&grass.layout, &camera_bind_group_layout],
+        );
+        pipelines.add_layout(
+            &device,
+            "water",
+            &[&water.layout, &camera_bind_group_layout],
+        );
+
+        let scene_defines = preprocessor::Defines::new(&["LIT"]);
+        let scene_pipeline = pipelines
+            .request(
+                &device,
+                &mut shaders,
+                &scene_pipeline(&scene_defines, sample_count),
+            )
+            .unwrap();
+
+        let debug_lines_pipeline = pipelines
+            .request(
+                &device,
+                &mut shaders,
+                &pipeline::PipelineBuilder::new(
+                    "Debug Line Pipeline",
+                    "debug_lines",
+                    "debug_lines.wgsl",
+                    postprocess::HDR_FORMAT,
+                )
+                .vertex_layout(debug_draw::LineVertex::layout())
+                .topology(wgpu::PrimitiveTopology::LineList)
+                .cull_mode(None)
+                .blend(Some(wgpu::BlendState::ALPHA_BLENDING))
+                .depth(
+                    texture::Texture::DEPTH_FORMAT,
+                    false,
+                    wgpu::CompareFunction::LessEqual,
+                )
+                .multisample(sample_count, false),
+            )
+            .unwrap();
+        let cutout_pipeline = pipelines
+            .request(&device, &mut shaders, &cutout_pipeline(sample_count))
+            .unwrap();
+        let transparent_pipeline = pipelines
+            .request(&device, &mut shaders, &transparent_pipeline(sample_count))
+            .unwrap();
+
+        let water_pipeline = pipelines
+            .request(&device, &mut shaders, &water_pipeline(sample_count))
+            .unwrap();
+        let reflection_pipeline = pipelines
+            .request(&device, &mut shaders, &reflection_pipeline())
+            .unwrap();
+
+        let grass_pipeline = pipelines
+            .request(&device, &mut shaders, &grass_pipeline(sample_count))
+            .unwrap();
+        let reflection_sky_pipeline = pipelines
+            .request(&device, &mut shaders, &sky_pipeline(1))
+            .unwrap();
+        let sky_pipeline = pipelines
+            .request(&device, &mut shaders, &sky_pipeline(sample_count))
+            .unwrap();
+
+        let debug = debug_draw::DebugDraw::new(&device);
+        let draw_list = geometry::DrawList::new(&device);
+
+        let occlusion = occlusion::OcclusionCuller::new(
+            &device,
+            &mut shaders,
+            &depth_texture,
+            sample_count,
+            reversed_z,
+            surface_cfg.width,
+            surface_cfg.height,
+            &mesh,
+        )
+        .unwrap();
+
         Self {
             surface,
             device,
@@ -229,20 +524,66 @@ impl State {
             surface_cfg,
             size,
             clear_color,
-            render_pipeline,
+            atmosphere,
+            sky_pipeline,
+            reflection_sky_pipeline,
+            pipelines,
+            scene_pipeline,
+            shaders,
+            scene_defines,
+            debug_view: debug_view::DebugView::Shaded,
+            line_polygon_mode,
             camera,
+            previous_camera: camera,
+            render_camera: camera,
             projection,
+            map_view: false,
+            reversed_z,
             camera_controller,
+            input: input::Input::new(bindings),
+            gamepads: gamepad::Gamepads::new(),
+            recorder: None,
+            player: None,
             camera_uniform,
             camera_buffer,
             camera_bind_group,
             frame_count: 0,
+            geometry,
             mesh,
+            terrain,
+            height_field,
+            visible: Vec::new(),
+            cull_stats: culling::Stats::default(),
+            draw_list,
+            occlusion,
+            objects,
+            world,
+            systems,
+            cutout_pipeline,
+            transparent_pipeline,
+            water,
+            grass,
+            grass_pipeline,
+            water_pipeline,
+            reflection_pipeline,
+            reflection_camera_buffer,
+            reflection_camera_bind_group,
+            debug,
+            debug_lines_pipeline,
+            gizmos: None,
             depth_texture,
+            sample_count,
+            post_process,
+            render_graph,
             delta_time: time::Duration::from_millis(13),
+            timestep: timestep::FixedTimestep::new(tick_rate),
+            previous_target: cgmath::Point3::new(0.0, 0.0, 0.0),
             last_frame_time: time::Instant::now(),
             start_time: time::Instant::now(),
             mouse_pressed: false,
+            cursor_mode: CursorMode::Captured,
+            applied_cursor_mode: None,
+            cursor_grab_failed: false,
         }
     }
 
@@ -256,8 +597,20 @@ impl State {
             self.depth_texture = texture::Texture::create_depth_texture(
                 &self.device,
                 &self.surface_cfg,
+                self.sample_count,
+                self.reversed_z,
                 "depth_texture",
             );
+            self.occlusion.resize(
+                &self.device,
+                &self.depth_texture,
+                new_size.width,
+                new_size.height,
+            );
+            self.post_process
+                .resize(&self.device, new_size.width, new_size.height);
+            self.render_graph
+                .resize(&self.device, new_size.width, new_size.height);
         }
     }
 
@@ -275,6 +628,26 @@ impl State {
                     self.resize(**new_inner_size);
                 }
                 WindowEvent::CloseRequested => return ControlFlow::Exit,
+                WindowEvent::MouseInput { state, button, .. } => {
+                    if *button == MouseButton::Left {
+                        let pressed = *state == ElementState::Pressed;
+                        if pressed
+                            && self.cursor_mode == CursorMode::Free
+                            && !self.cursor_grab_failed
+                        {
+                            self.cursor_mode = CursorMode::Captured;
+                        } else {
+                            self.mouse_pressed = pressed;
+                        }
+                    }
+                    return self.button_input(input::Button::Mouse(*button), state);
+                }
+                WindowEvent::Focused(false) => {
+                    self.cursor_mode = CursorMode::Free;
+                    self.mouse_pressed = false;
+                    // Releases happen in the other window now
+                    self.input.release_all();
+                }
                 _ => {}
             },
             Event::DeviceEvent {
@@ -286,36 +659,264 @@ impl State {
                     virtual_keycode: Some(keycode),
                     state,
                     ..
-                }) => return self.keyboard_input(keycode, state),
+                }) => return self.button_input(input::Button::Key(*keycode), state),
 
                 DeviceEvent::MouseWheel { delta, .. } => {
                     self.camera_controller.process_scroll(delta);
                 }
-                DeviceEvent::Button { button: 1, state } => {
-                    self.mouse_pressed = *state == ElementState::Pressed;
-                }
-                DeviceEvent::MouseMotion { delta } => {
+                DeviceEvent::MouseMotion { delta } if self.mouse_look() => {
                     self.camera_controller.process_mouse(delta.0, delta.1);
                 }
                 _ => {}
             },
+            Event::MainEventsCleared => return self.gamepad_input(),
             _ => {}
         };
 
         ControlFlow::Poll
     }
 
-    fn keyboard_input(&mut self, key: &VirtualKeyCode, state: &ElementState) -> ControlFlow {
-        self.camera_controller.process_keyboard(*key, *state);
+    /// Whether moving the mouse turns the camera: while captured or dragging,
+    /// but not while a camera path plays as it would pile up until the end.
+    fn mouse_look(&self) -> bool {
+        self.player.is_none() && (self.cursor_mode == CursorMode::Captured || self.mouse_pressed)
+    }
 
-        if *key == VirtualKeyCode::Escape {
-            ControlFlow::Exit
-        } else {
-            ControlFlow::Poll
+    /// Grabs and hides or frees the cursor of `window` after `cursor_mode`
+    /// changed. Without grabbing the cursor stays free, and looking around
+    /// works by dragging.
+    pub fn apply_cursor(&mut self, window: &Window) {
+        if self.applied_cursor_mode == Some(self.cursor_mode) {
+            return;
+        }
+
+        let captured = self.cursor_mode == CursorMode::Captured;
+        if let Err(e) = window.set_cursor_grab(captured) {
+            log::warn!(
+                "Can't grab the cursor, drag with the left mouse button to look around: {}",
+                e
+            );
+            self.cursor_grab_failed = true;
+            self.cursor_mode = CursorMode::Free;
+        }
+        window.set_cursor_visible(self.cursor_mode == CursorMode::Free);
+        self.applied_cursor_mode = Some(self.cursor_mode);
+    }
+
+    fn gamepad_input(&mut self) -> ControlFlow {
+        let mut flow = ControlFlow::Poll;
+        for (button, pressed) in self
+            .gamepads
+            .update(&mut self.input, self.last_frame_time.elapsed())
+        {
+            let state = if pressed {
+                ElementState::Pressed
+            } else {
+                ElementState::Released
+            };
+            if self.button_input(input::Button::Gamepad(button), &state) == ControlFlow::Exit {
+                flow = ControlFlow::Exit;
+            }
+        }
+        flow
+    }
+
+    fn button_input(&mut self, button: input::Button, state: &ElementState) -> ControlFlow {
+        let was_rebinding = self.input.rebinding();
+        let actions = self.input.process(button, *state == ElementState::Pressed);
+        if was_rebinding && !self.input.rebinding() {
+            if let Err(e) = self.input.bindings.save(BINDINGS_FILE) {
+                log::error!("{:?}", e);
+            }
+        }
+
+        let mut flow = ControlFlow::Poll;
+        for action in actions {
+            if action == input::Action::Exit {
+                flow = ControlFlow::Exit;
+            } else {
+                self.run_action(action);
+            }
+        }
+        flow
+    }
+
+    fn run_action(&mut self, action: input::Action) {
+        match action {
+            input::Action::Rebind => self.input.start_rebinding(),
+            input::Action::ReleaseCursor => self.cursor_mode = CursorMode::Free,
+            input::Action::CycleToneMap => {
+                if let Some(tone_map) = self.post_process.effect_mut::<postprocess::ToneMap>() {
+                    tone_map.operator = match tone_map.operator {
+                        postprocess::ToneMapOperator::Reinhard => {
+                            postprocess::ToneMapOperator::Aces
+                        }
+                        postprocess::ToneMapOperator::Aces => {
+                            postprocess::ToneMapOperator::Reinhard
+                        }
+                    };
+                }
+            }
+            input::Action::ToggleLighting => {
+                self.scene_defines = if self.scene_defines.contains("LIT") {
+                    preprocessor::Defines::default()
+                } else {
+                    preprocessor::Defines::default().with("LIT")
+                };
+                self.rebuild_scene_pipeline();
+            }
+            input::Action::ToggleGizmos => {
+                self.gizmos = match self.gizmos {
+                    Some(_) => None,
+                    None => Some(self.projection.proj_mat() * self.render_camera.view_mat()),
+                };
+            }
+            input::Action::ToggleOcclusion => {
+                self.occlusion.enabled = !self.occlusion.enabled;
+                log::info!("Occlusion culling: {}", self.occlusion.enabled);
+            }
+            input::Action::CycleFog => {
+                self.atmosphere.fog.mode = self.atmosphere.fog.mode.next();
+                log::info!("Fog: {:?}", self.atmosphere.fog.mode);
+            }
+            input::Action::ToggleSky => self.atmosphere.sky = !self.atmosphere.sky,
+            input::Action::RaiseWater | input::Action::LowerWater => {
+                self.water.level += if action == input::Action::RaiseWater {
+                    1.0
+                } else {
+                    -1.0
+                };
+                // Nothing grows under water
+                self.grass.rules.min_height = self.water.level + 0.5;
+                self.grass.dirty = true;
+            }
+            input::Action::CycleCameraMode => {
+                let height_field = &self.height_field;
+                self.camera_controller.set_mode(
+                    self.camera_controller.mode.next(),
+                    &self.camera,
+                    |x, z| height_field.height(x, z),
+                );
+                log::info!("Camera: {:?}", self.camera_controller.mode);
+            }
+            input::Action::CycleCamera => match game::cycle_camera(&mut self.world) {
+                Some(camera) => log::info!("Looking through {:?}", camera),
+                None => log::info!("Looking through the free camera"),
+            },
+            input::Action::ToggleMapView => {
+                self.map_view = !self.map_view;
+                self.projection = create_projection(
+                    self.map_view,
+                    self.reversed_z,
+                    self.size.width,
+                    self.size.height,
+                );
+                log::info!("Projection: {:?}", self.projection);
+            }
+            input::Action::ToggleReversedZ => self.set_reversed_z(!self.reversed_z),
+            input::Action::RecordPath => match self.recorder.take() {
+                Some(recorder) => match recorder.path.save(CAMERA_PATH_FILE) {
+                    Ok(()) => log::info!("Saved camera path to {}", CAMERA_PATH_FILE),
+                    Err(e) => log::error!("{:?}", e),
+                },
+                None => {
+                    log::info!("Recording camera path");
+                    self.recorder = Some(camera_path::Recorder::new(0.25));
+                }
+            },
+            input::Action::PlayPath => {
+                if self.player.take().is_none() {
+                    match camera_path::CameraPath::load(CAMERA_PATH_FILE) {
+                        Ok(path) => self.play(path, None),
+                        Err(e) => log::error!("{:?}", e),
+                    }
+                }
+            }
+            input::Action::ToggleGrass => self.grass.enabled = !self.grass.enabled,
+            input::Action::ToggleBloom => {
+                if self.post_process.remove("bloom").is_none() {
+                    let bloom = postprocess::Bloom::new(
+                        &self.device,
+                        &self.post_process.shader,
+                        self.surface_cfg.width,
+                        self.surface_cfg.height,
+                    );
+                    self.post_process.effects.insert(0, Box::new(bloom));
+                }
+                self.rebuild_render_graph();
+            }
+            input::Action::MoveVignette => {
+                let effects = &self.post_process.effects;
+                if let Some(index) = effects.iter().position(|e| e.name() == "vignette") {
+                    let index = (index + effects.len() - 1) % effects.len();
+                    self.post_process.reorder("vignette", index);
+                    let names: Vec<&str> = self
+                        .post_process
+                        .effects
+                        .iter()
+                        .map(|effect| effect.name())
+                        .collect();
+                    log::info!("Post processing: {}", names.join(", "));
+                    self.rebuild_render_graph();
+                }
+            }
+            _ => {
+                if let Some(view) = debug_view::DebugView::from_action(action) {
+                    log::info!("Debug view: {:?}", view);
+                    self.debug_view = view;
+                    self.rebuild_scene_pipeline();
+                }
+            }
+        }
+    }
+
+    /// Flies the camera along `path`, see `camera_path::Player`.
+    pub fn play(&mut self, path: camera_path::CameraPath, fixed_step: Option<time::Duration>) {
+        log::info!("Playing camera path of {}s", path.duration());
+        self.player = Some(camera_path::Player::new(path, fixed_step));
+    }
+
+    /// Switches the scene pipeline, e.g. after `scene_defines` or `debug_view`
+    /// changed. Keeps the old one if the shader doesn't compile.
+    pub fn rebuild_scene_pipeline(&mut self) {
+        let builder = self.debug_view.pipeline(
+            scene_pipeline(&self.scene_defines, self.sample_count),
+            self.line_polygon_mode,
+        );
+        match self
+            .pipelines
+            .request(&self.device, &mut self.shaders, &builder)
+        {
+            Ok(id) => self.scene_pipeline = id,
+            Err(e) => log::error!("{:?}", e),
+        }
+    }
+
+    /// Picks up shader edits made while running (debug builds only).
+    fn reload_shaders(&mut self) {
+        if !self.shaders.poll() {
+            return;
+        }
+
+        self.pipelines.reload(&self.device, &mut self.shaders);
+        self.occlusion.reload(&self.device, &mut self.shaders);
+        self.grass
+            .reload(&self.device, &mut self.shaders, &self.geometry);
+
+        match self.shaders.module(
+            &self.device,
+            "postprocess.wgsl",
+            &preprocessor::Defines::default(),
+            postprocess::ENTRY_POINTS,
+        ) {
+            Ok(shader) => self.post_process.reload(&self.device, shader),
+            Err(e) => log::error!("{:?}", e),
         }
     }
 
     pub fn update(&mut self) {
+        self.reload_shaders();
+
         self.delta_time = self.last_frame_time.elapsed();
         self.last_frame_time = time::Instant::now();
         //println!(
@@ -323,28 +924,398 @@ impl State {
         //    self.start_time.elapsed().as_secs_f32(),
         //    self.delta_time.as_secs_f32(),
         //);
-        self.camera_controller
-            .update_camera(&mut self.camera, self.delta_time);
+        // Camera paths step once per frame so `--play` renders the same
+        // frames every run, without interpolation
+        if let Some(player) = self.player.as_mut() {
+            if !player.advance(&mut self.camera, self.delta_time) {
+                log::info!("Camera path finished");
+                self.player = None;
+            }
+            self.previous_camera = self.camera;
+        }
+        for _ in 0..self.timestep.advance(self.delta_time) {
+            self.tick(self.timestep.tick);
+        }
+        let alpha = self.timestep.alpha();
+        self.render_camera = game::active_camera(&self.world, alpha)
+            .unwrap_or_else(|| self.previous_camera.lerp(&self.camera, alpha));
+        game::collect_objects(&self.world, alpha, &mut self.objects.objects);
+        if let Some((direction, intensity)) = game::sun(&self.world) {
+            self.atmosphere.sun_direction = direction;
+            self.atmosphere.sun_intensity = intensity;
+        }
+
+        if self.camera_controller.mode == camera::Mode::Follow {
+            // Stands in for a character until there is one
+            let previous = self.previous_target;
+            let target = previous + (self.camera_controller.target - previous) * alpha;
+            self.debug.sphere(
+                target + cgmath::Vector3::unit_y() * 0.5,
+                0.5,
+                debug_draw::YELLOW,
+            );
+        }
+        self.occlusion
+            .set_previous_camera(&self.queue, &self.camera_uniform);
         self.camera_uniform
-            .update_view_proj(&self.camera, &self.projection);
+            .update_view_proj(&self.render_camera, self.projection.as_ref());
         self.queue.write_buffer(
             &self.camera_buffer,
             0,
             bytemuck::cast_slice(&[self.camera_uniform]),
         );
+        self.atmosphere.update(&self.queue);
+        self.update_water();
+        self.grass
+            .scatter(&self.device, &self.queue, &self.geometry);
+        self.grass
+            .update(&self.queue, self.start_time.elapsed().as_secs_f32());
+        self.cull();
+        self.draw_gizmos();
         self.frame_count += 1;
     }
 
+    /// One step of the simulation, `dt` is always the tick length.
+    fn tick(&mut self, dt: time::Duration) {
+        self.previous_camera = self.camera;
+        self.previous_target = self.camera_controller.target;
+
+        if self.player.is_none() {
+            let height_field = &self.height_field;
+            self.camera_controller.process_input(&self.input);
+            self.camera_controller
+                .update_camera(&mut self.camera, dt, |x, z| height_field.height(x, z));
+        }
+        if let Some(recorder) = self.recorder.as_mut() {
+            recorder.record(&self.camera, dt);
+        }
+
+        let height_field = &self.height_field;
+        let tick = ecs::Tick {
+            dt,
+            ground: &|x, z| height_field.height(x, z),
+        };
+        self.systems.run(&mut self.world, &tick);
+    }
+
+    fn update_water(&mut self) {
+        let mut reflection_uniform = CameraUniform::new();
+        reflection_uniform.update_view_proj(
+            &self.render_camera.reflected(self.water.level),
+            self.projection.as_ref(),
+        );
+        reflection_uniform.clip_plane = self.water.clip_plane();
+        self.queue.write_buffer(
+            &self.reflection_camera_buffer,
+            0,
+            bytemuck::cast_slice(&[reflection_uniform]),
+        );
+        self.water
+            .update(&self.queue, self.start_time.elapsed().as_secs_f32());
+    }
+
+    /// Frustum culls the terrain chunks. While gizmos are shown this uses the
+    /// frozen frustum, so what gets culled can be seen from outside. Occlusion
+    /// culling happens later on the GPU and is not part of `cull_stats`.
+    fn cull(&mut self) {
+        let view_proj = self
+            .gizmos
+            .unwrap_or_else(|| self.projection.proj_mat() * self.render_camera.view_mat());
+        let frustum = culling::Frustum::from_matrix(view_proj);
+
+        self.cull_stats = culling::Stats::default();
+        self.visible = self.mesh.visible(&frustum, &mut self.cull_stats);
+        self.mesh.draw_commands(&self.visible, &mut self.draw_list);
+        self.objects.prepare(
+            &self.device,
+            &self.queue,
+            self.render_camera.pos,
+            &frustum,
+            &mut self.cull_stats,
+        );
+
+        if self.frame_count.is_multiple_of(100) {
+            log::debug!(
+                "Culling: {} drawn, {} culled",
+                self.cull_stats.drawn,
+                self.cull_stats.culled
+            );
+        }
+    }
+
+    fn draw_gizmos(&mut self) {
+        let frustum = match self.gizmos {
+            Some(frustum) => frustum,
+            None => return,
+        };
+
+        let origin = cgmath::Point3::new(0.0, 0.0, 0.0);
+        self.debug.axes(origin, 5.0);
+
+        let sun = self.atmosphere.sun_direction;
+        self.debug
+            .line(origin, origin + sun * 10.0, debug_draw::YELLOW);
+        self.debug
+            .sphere(origin + sun * 10.0, 0.5, debug_draw::YELLOW);
+
+        self.debug.frustum(frustum, [1.0, 1.0, 1.0, 1.0]);
+
+        // Terrain chunks, green if drawn and red if culled
+        let culling_frustum = culling::Frustum::from_matrix(frustum);
+        for submesh in self.mesh.submeshes.iter() {
+            let color = if culling_frustum.intersects(&submesh.bounds) {
+                debug_draw::GREEN
+            } else {
+                debug_draw::RED
+            };
+            self.debug
+                .aabb(submesh.bounds.aabb.min, submesh.bounds.aabb.max, color);
+        }
+        self.debug.normals(&self.terrain, 0.5, [0.0, 1.0, 1.0, 0.5]);
+    }
+
+    /// Switches every projection, pipeline, depth buffer and the occlusion
+    /// pyramid between the two depth conventions. Stays with the current one
+    /// if a shader doesn't build for the other.
+    pub fn set_reversed_z(&mut self, reversed_z: bool) {
+        if let Err(e) = self
+            .occlusion
+            .set_reversed_z(&self.device, &mut self.shaders, reversed_z)
+        {
+            log::error!("Reversed Z stays {}: {:?}", self.reversed_z, e);
+            return;
+        }
+        if let Err(e) = self
+            .pipelines
+            .set_reversed_z(&self.device, &mut self.shaders, reversed_z)
+        {
+            log::error!("Reversed Z stays {}: {:?}", self.reversed_z, e);
+            // Built before with these shaders, a failure here is a bug
+            self.occlusion
+                .set_reversed_z(&self.device, &mut self.shaders, self.reversed_z)
+                .expect("occlusion pipelines for the current depth convention");
+            return;
+        }
+
+        self.reversed_z = reversed_z;
+        self.projection =
+            create_projection(self.map_view, reversed_z, self.size.width, self.size.height);
+        // Recreates the depth buffer with the matching sampler
+        self.resize(self.size);
+        // A frozen frustum was for the other projection
+        self.gizmos = None;
+        log::info!("Reversed Z: {}", reversed_z);
+    }
+
+    /// The value depth buffers are cleared to, as far away as it gets.
+    pub fn far_depth(&self) -> f32 {
+        if self.projection.reversed_z() {
+            0.0
+        } else {
+            1.0
+        }
+    }
+
+    /// Has to be called whenever the passes change, e.g. effects are added.
+    pub fn rebuild_render_graph(&mut self) {
+        self.render_graph = passes::build_render_graph(
+            &self.device,
+            &self.surface_cfg,
+            self.sample_count,
+            &self.post_process,
+        );
+    }
+
     pub fn render(&mut self) -> Result<()> {
-        mesh::Mesh::draw(&self)
+        let frame = self.surface.get_current_frame()?.output;
+        let view = frame
+            .texture
+            .create_view(&wgpu::TextureViewDescriptor::default());
+
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Render Encoder"),
+            });
+
+        self.debug.upload(&self.device, &self.queue);
+        self.draw_list.upload(&self.device, &self.queue);
+
+        self.render_graph.execute(
+            self,
+            &mut encoder,
+            &[("backbuffer", &view), ("depth", &self.depth_texture.view)],
+        );
+
+        self.queue.submit(std::iter::once(encoder.finish()));
+        // The pyramid is only built while culling
+        self.occlusion.depth_valid = self.occlusion.enabled;
+        Ok(())
+    }
+}
+
+fn scene_pipeline(defines: &preprocessor::Defines, sample_count: u32) -> pipeline::PipelineBuilder {
+    let builder = pipeline::PipelineBuilder::new(
+        "Render Pipeline",
+        "scene",
+        "shader.wgsl",
+        postprocess::HDR_FORMAT,
+    )
+    .defines(defines.clone())
+    .vertex_layout(mesh::Vertex::layout())
+    .depth(
+        texture::Texture::DEPTH_FORMAT,
+        true,
+        wgpu::CompareFunction::Less,
+    )
+    .multisample(sample_count, false);
+
+    if defines.contains("INSTANCED") {
+        builder.vertex_layout(mesh::Instance::layout())
+    } else {
+        builder
     }
 }
 
+/// Perspective, or a top down orthographic view of the whole terrain for
+/// `map_view`. Reversed depth gets rid of the far plane too.
+fn create_projection(
+    map_view: bool,
+    reversed_z: bool,
+    width: u32,
+    height: u32,
+) -> Box<dyn camera::Projection> {
+    let map = camera::Orthographic::new(width, height, 400.0, -5000.0, 5000.0);
+    if map_view && reversed_z {
+        Box::new(camera::ReversedZ(map))
+    } else if map_view {
+        Box::new(map)
+    } else if reversed_z {
+        Box::new(camera::InfinitePerspective::new(
+            width,
+            height,
+            cgmath::Deg(90.0),
+            0.1,
+        ))
+    } else {
+        Box::new(camera::Perspective::new(
+            width,
+            height,
+            cgmath::Deg(90.0),
+            0.1,
+            10000.0,
+        ))
+    }
+}
+
+/// Alpha tested instanced objects, drawn with the opaque scene.
+fn cutout_pipeline(sample_count: u32) -> pipeline::PipelineBuilder {
+    let alpha_to_coverage = sample_count > 1;
+    let defines = preprocessor::Defines::new(&["LIT", "INSTANCED", "ALPHA_CUTOUT"]);
+    let defines = if alpha_to_coverage {
+        defines.with("ALPHA_TO_COVERAGE")
+    } else {
+        defines
+    };
+
+    scene_pipeline(&defines, sample_count)
+        .cull_mode(None)
+        .multisample(sample_count, alpha_to_coverage)
+}
+
+/// Alpha blended instanced objects, depth tested but not written so they
+/// don't hide each other.
+fn transparent_pipeline(sample_count: u32) -> pipeline::PipelineBuilder {
+    scene_pipeline(
+        &preprocessor::Defines::new(&["LIT", "INSTANCED"]),
+        sample_count,
+    )
+    .blend(Some(wgpu::BlendState::ALPHA_BLENDING))
+    .depth(
+        texture::Texture::DEPTH_FORMAT,
+        false,
+        wgpu::CompareFunction::Less,
+    )
+    .cull_mode(None)
+}
+
+/// The water surface, drawn after the opaque scene it refracts. Seen from
+/// above and below, so nothing is culled.
+fn water_pipeline(sample_count: u32) -> pipeline::PipelineBuilder {
+    pipeline::PipelineBuilder::new(
+        "Water Pipeline",
+        "water",
+        "water.wgsl",
+        postprocess::HDR_FORMAT,
+    )
+    .cull_mode(None)
+    .depth(
+        texture::Texture::DEPTH_FORMAT,
+        true,
+        wgpu::CompareFunction::Less,
+    )
+    .multisample(sample_count, false)
+}
+
+/// The terrain seen from below the water, for its reflection. From down there
+/// the top of the terrain faces away from the camera.
+fn reflection_pipeline() -> pipeline::PipelineBuilder {
+    scene_pipeline(&preprocessor::Defines::new(&["LIT", "CLIP_PLANE"]), 1)
+        .cull_mode(Some(wgpu::Face::Front))
+}
+
+/// Grass blades and foliage cards, both sides visible.
+fn grass_pipeline(sample_count: u32) -> pipeline::PipelineBuilder {
+    pipeline::PipelineBuilder::new(
+        "Grass Pipeline",
+        "grass",
+        "grass.wgsl",
+        postprocess::HDR_FORMAT,
+    )
+    .vertex_layout(grass::Instance::layout())
+    .cull_mode(None)
+    .depth(
+        texture::Texture::DEPTH_FORMAT,
+        true,
+        wgpu::CompareFunction::Less,
+    )
+    .multisample(sample_count, false)
+}
+
+/// The sky behind everything, drawn last on the far plane where nothing else
+/// was.
+fn sky_pipeline(sample_count: u32) -> pipeline::PipelineBuilder {
+    pipeline::PipelineBuilder::new("Sky Pipeline", "sky", "sky.wgsl", postprocess::HDR_FORMAT)
+        .cull_mode(None)
+        .depth(
+            texture::Texture::DEPTH_FORMAT,
+            false,
+            wgpu::CompareFunction::LessEqual,
+        )
+        .multisample(sample_count, false)
+}
+
+/// The requested MSAA sample count if every format supports it, otherwise
+/// the closest one below it that does. WebGPU guarantees 1x and 4x for the
+/// surface, depth and HDR formats. 2x and 8x are adapter specific, and wgpu
+/// 0.10 can't ask the adapter which formats allow them, so they aren't used.
+fn supported_sample_count(requested: u32) -> u32 {
+    let supported = match requested {
+        1 | 4 => return requested,
+        0..=3 => 1,
+        _ => 4,
+    };
+    log::warn!("{}x MSAA is not supported, using {}x", requested, supported);
+    supported
+}
+
 #[repr(C)]
 #[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
 pub struct CameraUniform {
     view_pos: [f32; 4],
     view_proj: [[f32; 4]; 4],
+    inv_view_proj: [[f32; 4]; 4],
+    clip_plane: [f32; 4],
 }
 
 impl CameraUniform {
@@ -353,11 +1324,19 @@ impl CameraUniform {
         Self {
             view_pos: [0.0; 4],
             view_proj: cgmath::Matrix4::identity().into(),
+            inv_view_proj: cgmath::Matrix4::identity().into(),
+            clip_plane: [0.0; 4],
         }
     }
 
-    fn update_view_proj(&mut self, camera: &camera::Camera, projection: &camera::Projection) {
+    fn update_view_proj(&mut self, camera: &camera::Camera, projection: &dyn camera::Projection) {
         self.view_pos = camera.pos.to_homogeneous().into();
-        self.view_proj = (projection.proj_mat() * camera.view_mat()).into();
+        use cgmath::SquareMatrix;
+        let view_proj = projection.proj_mat() * camera.view_mat();
+        self.view_proj = view_proj.into();
+        self.inv_view_proj = view_proj
+            .invert()
+            .unwrap_or_else(cgmath::Matrix4::identity)
+            .into();
     }
 }
@@ -0,0 +1,78 @@
+use std::time::Duration;
+
+/// Runs the simulation in ticks of the same length however long frames
+/// take, so it behaves the same at any frame rate. Frames render between the
+/// last two ticks, `alpha` of the way.
+#[derive(Debug)]
+pub struct FixedTimestep {
+    pub tick: Duration,
+    /// The most ticks a frame catches up on. A frame that is further behind
+    /// drops the rest, the game slows down instead of every frame running
+    /// more ticks and taking longer still (the spiral of death).
+    pub max_ticks: u32,
+    accumulator: Duration,
+}
+
+impl FixedTimestep {
+    /// `rate` ticks per second.
+    pub fn new(rate: f64) -> Self {
+        Self {
+            tick: Duration::from_secs_f64(1.0 / rate),
+            max_ticks: 8,
+            accumulator: Duration::ZERO,
+        }
+    }
+
+    /// Adds a frame's time, returning how many ticks to run.
+    pub fn advance(&mut self, frame_time: Duration) -> u32 {
+        self.accumulator += frame_time;
+
+        let mut ticks = 0;
+        while self.accumulator >= self.tick && ticks < self.max_ticks {
+            self.accumulator -= self.tick;
+            ticks += 1;
+        }
+        if self.accumulator >= self.tick {
+            log::debug!("{:?} behind, skipping it", self.accumulator);
+            self.accumulator = Duration::ZERO;
+        }
+        ticks
+    }
+
+    /// How far from the last tick to the next one time is, 0 to 1.
+    pub fn alpha(&self) -> f32 {
+        (self.accumulator.as_secs_f64() / self.tick.as_secs_f64()) as f32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ticks_accumulate_across_frames() {
+        let mut timestep = FixedTimestep::new(100.0);
+        let frame = Duration::from_millis(4);
+        let ticks: Vec<u32> = (0..5).map(|_| timestep.advance(frame)).collect();
+        assert_eq!(ticks, vec![0, 0, 1, 0, 1]);
+    }
+
+    #[test]
+    fn long_frames_drop_time_past_max_ticks() {
+        let mut timestep = FixedTimestep::new(100.0);
+        assert_eq!(timestep.advance(Duration::from_secs(1)), timestep.max_ticks);
+        assert_eq!(timestep.alpha(), 0.0);
+        // Nothing left to catch up on
+        assert_eq!(timestep.advance(Duration::from_millis(5)), 0);
+    }
+
+    #[test]
+    fn alpha_stays_below_one() {
+        let mut timestep = FixedTimestep::new(60.0);
+        for i in 0..1000 {
+            timestep.advance(Duration::from_micros(1000 + i * 37));
+            let alpha = timestep.alpha();
+            assert!((0.0..1.0).contains(&alpha), "{}", alpha);
+        }
+    }
+}
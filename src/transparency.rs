@@ -0,0 +1,194 @@
+use cgmath::*;
+use std::ops::Range;
+
+use crate::culling;
+use crate::geometry;
+use crate::mesh;
+use crate::texture;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum AlphaMode {
+    /// Blended over what's behind it, drawn back to front after the opaque
+    /// scene without writing depth.
+    Blend,
+    /// Alpha tested, e.g. foliage. Drawn with the opaque scene, using alpha to
+    /// coverage when multisampling.
+    Cutout,
+}
+
+pub struct Object {
+    /// Index into `Objects::meshes`.
+    pub mesh: usize,
+    pub transform: Matrix4<f32>,
+    /// Multiplied with the texture, alpha included.
+    pub color: [f32; 4],
+    pub mode: AlphaMode,
+}
+
+/// Meshes drawn with a transform and color each, as instances of the
+/// INSTANCED shader variant. Blended ones are sorted by distance to the
+/// camera every frame.
+pub struct Objects {
+    pub meshes: Vec<mesh::Mesh>,
+    pub objects: Vec<Object>,
+    instances: wgpu::Buffer,
+    // In instances
+    capacity: usize,
+    // Mesh and instances of every draw this frame
+    cutout_draws: Vec<(usize, Range<u32>)>,
+    blend_draws: Vec<(usize, Range<u32>)>,
+}
+
+impl Objects {
+    pub fn new(device: &wgpu::Device) -> Self {
+        let capacity = 64;
+        Self {
+            meshes: Vec::new(),
+            objects: Vec::new(),
+            instances: Self::create_buffer(device, capacity),
+            capacity,
+            cutout_draws: Vec::new(),
+            blend_draws: Vec::new(),
+        }
+    }
+
+    fn create_buffer(device: &wgpu::Device, capacity: usize) -> wgpu::Buffer {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Object Instance Buffer"),
+            size: (capacity * std::mem::size_of::<mesh::Instance>()) as u64,
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        })
+    }
+
+    /// Culls, sorts and uploads the instances for this frame.
+    pub fn prepare(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        camera_pos: Point3<f32>,
+        frustum: &culling::Frustum,
+        stats: &mut culling::Stats,
+    ) {
+        let mut cutout = Vec::new();
+        let mut blend = Vec::new();
+        for (i, object) in self.objects.iter().enumerate() {
+            let aabb = self.meshes[object.mesh]
+                .bounds
+                .aabb
+                .transformed(object.transform);
+            if !frustum.intersects_aabb(&aabb) {
+                stats.culled += 1;
+                continue;
+            }
+            stats.drawn += 1;
+
+            let distance = aabb.center().distance2(camera_pos);
+            match object.mode {
+                AlphaMode::Cutout => cutout.push((i, distance)),
+                AlphaMode::Blend => blend.push((i, distance)),
+            }
+        }
+
+        // Cutouts are grouped by mesh to batch them, blended objects go from
+        // the farthest to the nearest
+        cutout.sort_by_key(|&(i, _)| self.objects[i].mesh);
+        blend.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
+
+        let mut instances = Vec::with_capacity(cutout.len() + blend.len());
+        self.cutout_draws = self.batch(&cutout, &mut instances);
+        self.blend_draws = self.batch(&blend, &mut instances);
+
+        if instances.len() > self.capacity {
+            self.capacity = instances.len().next_power_of_two();
+            self.instances = Self::create_buffer(device, self.capacity);
+        }
+        queue.write_buffer(&self.instances, 0, bytemuck::cast_slice(&instances));
+    }
+
+    /// Appends the instances of `order`, returning one draw per run of the
+    /// same mesh.
+    fn batch(
+        &self,
+        order: &[(usize, f32)],
+        instances: &mut Vec<mesh::Instance>,
+    ) -> Vec<(usize, Range<u32>)> {
+        let mut draws: Vec<(usize, Range<u32>)> = Vec::new();
+        for &(i, _) in order {
+            let object = &self.objects[i];
+            let instance = instances.len() as u32;
+            instances.push(mesh::Instance {
+                model: object.transform.into(),
+                color: object.color,
+            });
+
+            match draws.last_mut() {
+                Some((mesh, range)) if *mesh == object.mesh => range.end = instance + 1,
+                _ => draws.push((object.mesh, instance..instance + 1)),
+            }
+        }
+        draws
+    }
+
+    fn draw<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>, draws: &[(usize, Range<u32>)]) {
+        if draws.is_empty() {
+            return;
+        }
+
+        render_pass.set_vertex_buffer(1, self.instances.slice(..));
+        for (mesh, instances) in draws {
+            let mesh = &self.meshes[*mesh];
+            mesh.bind(render_pass);
+            render_pass.draw_indexed(mesh.indices.clone(), 0, instances.clone());
+        }
+    }
+
+    /// Draws the cutout objects. The pipeline, camera bind group and
+    /// `GeometryPool` are up to the caller.
+    pub fn draw_cutout<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) {
+        self.draw(render_pass, &self.cutout_draws);
+    }
+
+    /// Same as `draw_cutout` for the blended objects, back to front.
+    pub fn draw_blended<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) {
+        self.draw(render_pass, &self.blend_draws);
+    }
+}
+
+/// Leaves made of soft edged blobs with fully transparent gaps in between.
+pub fn foliage_texture(device: &wgpu::Device, queue: &wgpu::Queue) -> texture::Texture {
+    const SIZE: u32 = 128;
+    let image = image::RgbaImage::from_fn(SIZE, SIZE, |x, y| {
+        let (u, v) = (x as f32 / SIZE as f32, y as f32 / SIZE as f32);
+        let leaf = ((u * 9.0).sin() * (v * 7.0 + u * 3.0).sin()).abs();
+        // Thinner towards the top, like a plant
+        let alpha = if leaf > 0.3 + 0.5 * (1.0 - v) { 255 } else { 0 };
+        let shade = (120.0 + 100.0 * leaf) as u8;
+        image::Rgba([shade / 3, shade, shade / 4, alpha])
+    });
+
+    texture::Texture::from_image(
+        device,
+        queue,
+        &image::DynamicImage::ImageRgba8(image),
+        Some("Foliage Texture"),
+    )
+    .unwrap()
+}
+
+/// The demo scene's glass pane and foliage card meshes, baked into the pool
+/// and added to `objects`. Returns their indices into `Objects::meshes`.
+pub fn demo_meshes(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    pool: &mut geometry::GeometryPool,
+    objects: &mut Objects,
+    quad: mesh::Descriptor,
+    foliage: mesh::Descriptor,
+) -> (usize, usize) {
+    let glass = objects.meshes.len();
+    objects.meshes.push(quad.bake(device, queue, pool));
+    let leaves = objects.meshes.len();
+    objects.meshes.push(foliage.bake(device, queue, pool));
+    (glass, leaves)
+}
@@ -0,0 +1,179 @@
+use crate::culling;
+use crate::texture;
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct WaterUniform {
+    params: [f32; 4],
+    extent: [f32; 4],
+    terrain: [f32; 4],
+    absorption: [f32; 4],
+    deep_color: [f32; 4],
+}
+
+/// A flat plane of water over the terrain with waves, a planar reflection,
+/// refraction of the scene below it and foam near the shore. The shore is
+/// found by sampling the heightmap, so it only knows about the terrain.
+pub struct Water {
+    pub level: f32,
+    pub wave_strength: f32,
+    /// How shallow the water has to be for foam.
+    pub foam_width: f32,
+    /// Fraction of red, green and blue absorbed per unit of water.
+    pub absorption: [f32; 3],
+    /// What is left when everything is absorbed.
+    pub deep_color: [f32; 3],
+    // Min x, min z, max x, max z
+    extent: [f32; 4],
+    // World size of the heightmap along x and z and its height scale
+    terrain: [f32; 4],
+    uniform: wgpu::Buffer,
+    pub layout: wgpu::BindGroupLayout,
+    height_map: wgpu::TextureView,
+    sampler: wgpu::Sampler,
+}
+
+impl Water {
+    /// `height_map` is the texture the terrain was generated from, in a
+    /// non-sRGB format so it reads the raw heights. It covers `map_size` in
+    /// world units with texel values scaled by `height_scale`.
+    pub fn new(
+        device: &wgpu::Device,
+        height_map: &texture::Texture,
+        map_size: (f32, f32),
+        height_scale: f32,
+        bounds: &culling::Bounds,
+    ) -> Self {
+        let aabb = bounds.aabb;
+        let margin = 50.0;
+
+        let texture_entry = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                multisampled: false,
+                sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                view_dimension: wgpu::TextureViewDimension::D2,
+            },
+            count: None,
+        };
+
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Water Bind Group Layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                texture_entry(1),
+                texture_entry(2),
+                texture_entry(3),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 4,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler {
+                        comparison: false,
+                        filtering: true,
+                    },
+                    count: None,
+                },
+            ],
+        });
+
+        Self {
+            // A third of the way up the terrain
+            level: aabb.min.y + (aabb.max.y - aabb.min.y) * 0.3,
+            wave_strength: 1.0,
+            foam_width: 1.5,
+            absorption: [0.12, 0.05, 0.03],
+            deep_color: [0.0, 0.04, 0.08],
+            extent: [
+                aabb.min.x - margin,
+                aabb.min.z - margin,
+                aabb.max.x + margin,
+                aabb.max.z + margin,
+            ],
+            terrain: [map_size.0, map_size.1, height_scale, 0.0],
+            uniform: device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("Water Uniform Buffer"),
+                size: std::mem::size_of::<WaterUniform>() as u64,
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            }),
+            layout,
+            height_map: height_map
+                .texture
+                .create_view(&wgpu::TextureViewDescriptor::default()),
+            sampler: device.create_sampler(&wgpu::SamplerDescriptor {
+                address_mode_u: wgpu::AddressMode::ClampToEdge,
+                address_mode_v: wgpu::AddressMode::ClampToEdge,
+                address_mode_w: wgpu::AddressMode::ClampToEdge,
+                mag_filter: wgpu::FilterMode::Linear,
+                min_filter: wgpu::FilterMode::Linear,
+                ..Default::default()
+            }),
+        }
+    }
+
+    /// Horizontal plane the reflection is mirrored by, for `CameraUniform::clip_plane`.
+    pub fn clip_plane(&self) -> [f32; 4] {
+        [0.0, 1.0, 0.0, -self.level]
+    }
+
+    pub fn update(&self, queue: &wgpu::Queue, time: f32) {
+        let rgb = |c: [f32; 3]| [c[0], c[1], c[2], 0.0];
+        queue.write_buffer(
+            &self.uniform,
+            0,
+            bytemuck::cast_slice(&[WaterUniform {
+                params: [self.level, time, self.wave_strength, self.foam_width],
+                extent: self.extent,
+                terrain: self.terrain,
+                absorption: rgb(self.absorption),
+                deep_color: rgb(self.deep_color),
+            }]),
+        );
+    }
+
+    /// Group 0 of the water pipeline. `scene` is a copy of the opaque scene
+    /// and `reflection` what the mirrored camera sees.
+    pub fn bind_group(
+        &self,
+        device: &wgpu::Device,
+        scene: &wgpu::TextureView,
+        reflection: &wgpu::TextureView,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &self.layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: self.uniform.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(scene),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::TextureView(reflection),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: wgpu::BindingResource::TextureView(&self.height_map),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: wgpu::BindingResource::Sampler(&self.sampler),
+                },
+            ],
+            label: Some("Water Bind Group"),
+        })
+    }
+}
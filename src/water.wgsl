@@ -0,0 +1,127 @@
+#include "camera.wgsl"
+
+[[block]]
+struct WaterUniform {
+    // Water level, time in seconds, wave strength, foam width
+    params: vec4<f32>;
+    // Min x, min z, max x, max z of the plane
+    extent: vec4<f32>;
+    // World size of the heightmap along x and z, height of a texel value of 1
+    terrain: vec4<f32>;
+    // How fast each color channel fades per unit of water, in rgb
+    absorption: vec4<f32>;
+    deep_color: vec4<f32>;
+};
+
+[[group(0), binding(0)]]
+var<uniform> water: WaterUniform;
+
+[[group(0), binding(1)]]
+var t_scene: texture_2d<f32>;
+
+[[group(0), binding(2)]]
+var t_reflection: texture_2d<f32>;
+
+[[group(0), binding(3)]]
+var t_height: texture_2d<f32>;
+
+[[group(0), binding(4)]]
+var s_linear: sampler;
+
+[[group(1), binding(0)]]
+var<uniform> camera: CameraUniform;
+
+[[group(1), binding(1)]]
+var<uniform> atmosphere: AtmosphereUniform;
+
+#include "atmosphere.wgsl"
+
+struct VertexOutput {
+    [[builtin(position)]] clip_position: vec4<f32>;
+    [[location(0)]] world_position: vec3<f32>;
+};
+
+// One quad over the whole extent
+[[stage(vertex)]]
+fn main([[builtin(vertex_index)]] vertex_index: u32) -> VertexOutput {
+    var corners = array<vec2<f32>, 6>(
+        vec2<f32>(0.0, 0.0),
+        vec2<f32>(0.0, 1.0),
+        vec2<f32>(1.0, 1.0),
+        vec2<f32>(0.0, 0.0),
+        vec2<f32>(1.0, 1.0),
+        vec2<f32>(1.0, 0.0),
+    );
+    let corner = corners[vertex_index];
+    let xz = mix(water.extent.xy, water.extent.zw, corner);
+
+    var out: VertexOutput;
+    out.world_position = vec3<f32>(xz.x, water.params.x, xz.y);
+    out.clip_position = camera.view_proj * vec4<f32>(out.world_position, 1.0);
+    return out;
+}
+
+// Sum of a few travelling sine waves, returns the slope along x and z
+fn wave_slope(xz: vec2<f32>, time: f32) -> vec2<f32> {
+    var slope = vec2<f32>(0.0, 0.0);
+    var directions = array<vec3<f32>, 4>(
+        // Direction xy, frequency z
+        vec3<f32>(0.8, 0.6, 0.35),
+        vec3<f32>(-0.5, 0.9, 0.6),
+        vec3<f32>(0.2, -1.0, 1.1),
+        vec3<f32>(-0.9, -0.3, 1.9),
+    );
+    for (var i: i32 = 0; i < 4; i = i + 1) {
+        let wave = directions[i];
+        let direction = normalize(wave.xy);
+        let phase = dot(direction, xz) * wave.z + time * (1.0 + f32(i) * 0.4);
+        slope = slope + direction * cos(phase) / wave.z;
+    }
+    return slope * 0.25;
+}
+
+fn terrain_height(xz: vec2<f32>) -> f32 {
+    let uv = xz / water.terrain.xy;
+    // Unorm, the same 0..1 the terrain's vertex heights were scaled from
+    let value = textureSampleLevel(t_height, s_linear, uv, 0.0).r;
+    return value * water.terrain.z;
+}
+
+[[stage(fragment)]]
+fn main(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    let level = water.params.x;
+    let time = water.params.y;
+    let strength = water.params.z;
+    let foam_width = water.params.w;
+
+    let slope = wave_slope(in.world_position.xz, time) * strength;
+    let normal = normalize(vec3<f32>(-slope.x, 1.0, -slope.y));
+    let to_camera = normalize(camera.view_pos.xyz - in.world_position);
+
+    let size = vec2<f32>(textureDimensions(t_scene));
+    let screen_uv = in.clip_position.xy / size;
+    let distortion = normal.xz * 0.03;
+
+    // The reflection is rendered by a mirrored camera, upside down
+    let reflection_uv = vec2<f32>(screen_uv.x, 1.0 - screen_uv.y) + distortion;
+    let reflection = textureSample(t_reflection, s_linear, clamp(reflection_uv, vec2<f32>(0.001, 0.001), vec2<f32>(0.999, 0.999))).rgb;
+    let refraction = textureSample(t_scene, s_linear, clamp(screen_uv + distortion, vec2<f32>(0.001, 0.001), vec2<f32>(0.999, 0.999))).rgb;
+
+    // How much water the light went through on its way to the camera
+    let depth = max(level - terrain_height(in.world_position.xz), 0.0);
+    let path = depth / max(to_camera.y, 0.1);
+    let transmittance = exp(-water.absorption.rgb * path);
+    let underwater = mix(water.deep_color.rgb, refraction, transmittance);
+
+    // Schlick's approximation, water reflects about 2% head on
+    let fresnel = 0.02 + 0.98 * pow(1.0 - max(dot(normal, to_camera), 0.0), 5.0);
+    var color = mix(underwater, reflection, fresnel);
+
+    // Foam where the terrain gets close to the surface, broken up by the waves
+    let shore = clamp(1.0 - depth / foam_width, 0.0, 1.0);
+    let ripples = 0.5 + 0.5 * sin(depth * 8.0 - time * 2.0 + (slope.x + slope.y) * 20.0);
+    let foam = shore * shore * ripples;
+    color = mix(color, vec3<f32>(1.0, 1.0, 1.0), foam);
+
+    return vec4<f32>(apply_atmosphere(color, in.world_position, camera.view_pos.xyz), 1.0);
+}
@@ -0,0 +1,58 @@
+#include "camera.wgsl"
+
+// Fallback wireframe for adapters without PolygonMode::Line. Draws the mesh
+// without an index buffer, pulling vertices by hand so every triangle corner
+// gets its own barycentric coordinate.
+
+[[block]]
+struct Floats {
+    data: [[stride(4)]] array<f32>;
+};
+
+[[block]]
+struct Indices {
+    data: [[stride(4)]] array<u32>;
+};
+
+[[group(0), binding(0)]]
+var<storage, read> vertices: Floats;
+
+[[group(0), binding(1)]]
+var<storage, read> indices: Indices;
+
+[[group(1), binding(0)]]
+var<uniform> camera: CameraUniform;
+
+struct VertexOutput {
+    [[builtin(position)]] clip_position: vec4<f32>;
+    [[location(0)]] barycentric: vec3<f32>;
+};
+
+[[stage(vertex)]]
+fn main([[builtin(vertex_index)]] vertex_index: u32) -> VertexOutput {
+    // mesh::Vertex is 8 floats: position, normal, uv
+    let base = indices.data[vertex_index] * 8u;
+    let position = vec3<f32>(vertices.data[base], vertices.data[base + 1u], vertices.data[base + 2u]);
+
+    let corner = vertex_index % 3u;
+    var out: VertexOutput;
+    out.barycentric = vec3<f32>(
+        select(0.0, 1.0, corner == 0u),
+        select(0.0, 1.0, corner == 1u),
+        select(0.0, 1.0, corner == 2u),
+    );
+    out.clip_position = camera.view_proj * vec4<f32>(position, 1.0);
+    return out;
+}
+
+[[stage(fragment)]]
+fn main(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    // About a pixel and a half wide no matter the distance
+    let width = fwidth(in.barycentric) * 1.5;
+    let edges = clamp(in.barycentric / width, vec3<f32>(0.0, 0.0, 0.0), vec3<f32>(1.0, 1.0, 1.0));
+    let edge = 1.0 - min(edges.x, min(edges.y, edges.z));
+    if (edge < 0.01) {
+        discard;
+    }
+    return vec4<f32>(edge, edge, edge, 1.0);
+}